@@ -22,6 +22,34 @@ init()
       wasm.reset();
     });
 
+    document.getElementById("full").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
+    document.getElementById("sample-rate").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
+    document.getElementById("checkpoint").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
+    document.getElementById("mismatches").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
+    document.getElementById("scheme").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
+    document.getElementById("start").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
+    document.getElementById("order").addEventListener("change", (event) => {
+      wasm.reset();
+    });
+
     document.getElementById("prev").addEventListener("click", (event) => {
       wasm.prev();
     });
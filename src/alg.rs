@@ -1,11 +1,300 @@
+pub mod approx_search;
 pub mod bibwt;
 pub mod bwt;
+pub mod dc3;
+pub mod lcp;
+pub mod lcp_tree;
+pub mod prefix_doubling;
+pub mod sais;
 pub mod suffix_array;
+pub mod suffix_automaton;
+pub mod ukkonen;
+pub mod wavelet_tree;
 
-use crate::canvas::Canvas;
+use std::fmt::{self, Display};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::canvas::{to_label, Canvas, Color};
+
+/// Why an input can not be visualized.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InputError {
+    /// The string does not end in the sentinel `$`.
+    MissingSentinel,
+    /// The string has no characters before the sentinel.
+    EmptyString,
+    /// The sentinel `$` also occurs at this position before the end.
+    SentinelInString(usize),
+    /// A character that is not printable ASCII or sorts before `$`.
+    InvalidChar(u8),
+    /// The sentinel `$` occurs in the query at this position.
+    SentinelInQuery(usize),
+    /// A character of the query that does not occur in the string.
+    QueryCharNotInString(u8),
+    /// The query is longer than can be shown.
+    QueryTooLong { len: usize, max: usize },
+    /// The suffix array sampling rate is zero.
+    ZeroSampleRate,
+    /// The interval between stored rows of Occ is zero.
+    ZeroCheckpoint,
+    /// The search scheme can not be parsed or is not valid for bidirectional search.
+    InvalidScheme(String),
+    /// The extension order does not fit the query.
+    InvalidOrder(String),
+    /// The name does not match any of the visualizations.
+    UnknownAlgorithm(String),
+}
+
+impl Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ch = |c: u8| {
+            if c.is_ascii_graphic() {
+                format!("'{}'", c as char)
+            } else {
+                format!("byte {c:#04x}")
+            }
+        };
+        match self {
+            InputError::MissingSentinel => write!(f, "The string must end in $."),
+            InputError::EmptyString => write!(f, "The string must not be empty."),
+            InputError::SentinelInString(i) => write!(
+                f,
+                "The string contains $ at position {i}, but it may only occur at the end."
+            ),
+            InputError::InvalidChar(c) => write!(
+                f,
+                "The string contains {}; only printable ASCII characters after $ are supported.",
+                ch(*c)
+            ),
+            InputError::SentinelInQuery(i) => write!(
+                f,
+                "The query contains $ at position {i}, but $ may only end the string."
+            ),
+            InputError::QueryCharNotInString(c) => {
+                write!(
+                    f,
+                    "The query contains {}, which is not in the string.",
+                    ch(*c)
+                )
+            }
+            InputError::QueryTooLong { len, max } => write!(
+                f,
+                "The query has length {len}, but at most {max} characters can be shown."
+            ),
+            InputError::ZeroSampleRate => write!(f, "The sampling rate must be at least 1."),
+            InputError::ZeroCheckpoint => write!(f, "The checkpoint interval must be at least 1."),
+            InputError::InvalidScheme(reason) => write!(f, "The search scheme {reason}"),
+            InputError::InvalidOrder(reason) => write!(f, "The extension order {reason}"),
+            InputError::UnknownAlgorithm(name) => write!(f, "Unknown algorithm {name}."),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Check that `s` is a non-empty string of printable ASCII characters ending in a unique `$`.
+pub fn validate_string(s: &[u8]) -> Result<(), InputError> {
+    let Some((&last, s)) = s.split_last() else {
+        return Err(InputError::MissingSentinel);
+    };
+    if last != b'$' {
+        return Err(InputError::MissingSentinel);
+    }
+    if s.is_empty() {
+        return Err(InputError::EmptyString);
+    }
+    if let Some(i) = s.iter().position(|&c| c == b'$') {
+        return Err(InputError::SentinelInString(i));
+    }
+    if let Some(&c) = s.iter().find(|&&c| !c.is_ascii_graphic() || c < b'$') {
+        return Err(InputError::InvalidChar(c));
+    }
+    Ok(())
+}
+
+/// Check that `q` has at most `max` characters, which all occur in `s` and are not `$`.
+pub fn validate_query(s: &[u8], q: &[u8], max: usize) -> Result<(), InputError> {
+    if q.len() > max {
+        return Err(InputError::QueryTooLong { len: q.len(), max });
+    }
+    if let Some(i) = q.iter().position(|&c| c == b'$') {
+        return Err(InputError::SentinelInQuery(i));
+    }
+    if let Some(&c) = q.iter().find(|c| !s.contains(c)) {
+        return Err(InputError::QueryCharNotInString(c));
+    }
+    Ok(())
+}
+
+/// What happens in a single state, as text.
+pub struct Explanation {
+    /// Short summary, as drawn on the canvas.
+    pub title: String,
+    pub description: String,
+}
+
+impl Explanation {
+    pub fn new(title: impl ToString, description: impl ToString) -> Self {
+        Self {
+            title: title.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
 
 pub trait Viz {
     fn canvas_size(&self) -> (usize, usize);
     fn num_states(&self) -> usize;
     fn draw(&self, state: usize, canvas: &mut Box<dyn Canvas>) -> bool;
+    fn explanation(&self, state: usize) -> Explanation;
+    /// The data underlying a state, as a JSON object.
+    fn trace(&self, state: usize) -> Value;
+    /// Names for the algorithm-specific colors used in `draw`.
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![]
+    }
+}
+
+/// A JSON object with one entry per character of the alphabet.
+pub fn char_map<T: Serialize>(
+    alph: &[u8],
+    values: impl IntoIterator<Item = T>,
+) -> Map<String, Value> {
+    alph.iter()
+        .zip(values)
+        .map(|(&c, v)| (to_label(c), serde_json::to_value(v).unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bibwt, bwt::BWT, dc3::DC3, validate_query, validate_string, InputError};
+
+    #[test]
+    fn invalid_strings() {
+        assert_eq!(validate_string(b""), Err(InputError::MissingSentinel));
+        assert_eq!(validate_string(b"ACGT"), Err(InputError::MissingSentinel));
+        assert_eq!(validate_string(b"$"), Err(InputError::EmptyString));
+        assert_eq!(
+            validate_string(b"AC$GT$"),
+            Err(InputError::SentinelInString(2))
+        );
+        assert_eq!(
+            validate_string(b"AC GT$"),
+            Err(InputError::InvalidChar(b' '))
+        );
+        assert_eq!(
+            validate_string(b"AC#GT$"),
+            Err(InputError::InvalidChar(b'#'))
+        );
+        assert_eq!(validate_string(b"ACGT$"), Ok(()));
+        // The constructors validate their input.
+        assert!(matches!(
+            DC3::new(b"ACGT".to_vec()),
+            Err(InputError::MissingSentinel)
+        ));
+    }
+
+    #[test]
+    fn invalid_queries() {
+        let s = b"BANANA$";
+        assert_eq!(
+            validate_query(s, b"NAB", 2),
+            Err(InputError::QueryTooLong { len: 3, max: 2 })
+        );
+        assert_eq!(
+            validate_query(s, b"A$", 7),
+            Err(InputError::SentinelInQuery(1))
+        );
+        assert_eq!(
+            validate_query(s, b"ANT", 7),
+            Err(InputError::QueryCharNotInString(b'T'))
+        );
+        assert_eq!(validate_query(s, b"ANA", 7), Ok(()));
+        assert_eq!(validate_query(s, b"", 7), Ok(()));
+    }
+
+    #[test]
+    fn invalid_bwt_parameters() {
+        let new = |sample_rate, checkpoint| {
+            BWT::new(
+                b"BANANA$".to_vec(),
+                b"ANA".to_vec(),
+                sample_rate,
+                checkpoint,
+            )
+            .err()
+        };
+        assert_eq!(new(0, 1), Some(InputError::ZeroSampleRate));
+        assert_eq!(new(4, 0), Some(InputError::ZeroCheckpoint));
+        assert_eq!(new(4, 1), None);
+    }
+
+    #[test]
+    fn invalid_orders_and_schemes() {
+        assert!(matches!(
+            bibwt::parse_order("LXR"),
+            Err(InputError::InvalidOrder(_))
+        ));
+        assert!(matches!(
+            bibwt::parse_scheme("12/00"),
+            Err(InputError::InvalidScheme(_))
+        ));
+        assert!(matches!(
+            bibwt::parse_scheme("12/01/00"),
+            Err(InputError::InvalidScheme(_))
+        ));
+        let new = |start, order: &str, scheme: &str| {
+            let order = bibwt::parse_order(order).unwrap();
+            let scheme = match scheme {
+                "" => vec![],
+                scheme => bibwt::parse_scheme(scheme).unwrap(),
+            };
+            bibwt::BiBWT::new(b"BANANA$".to_vec(), b"ANA".to_vec(), start, order, scheme).err()
+        };
+        assert!(matches!(
+            new(Some(3), "", ""),
+            Some(InputError::InvalidOrder(_))
+        ));
+        assert!(matches!(
+            new(None, "L", ""),
+            Some(InputError::InvalidOrder(_))
+        ));
+        assert!(matches!(
+            new(Some(0), "LR", ""),
+            Some(InputError::InvalidOrder(_))
+        ));
+        assert!(matches!(
+            new(Some(1), "", "12/00/01"),
+            Some(InputError::InvalidOrder(_))
+        ));
+        assert!(matches!(
+            new(None, "", "1234/0000/0001"),
+            Some(InputError::InvalidScheme(_))
+        ));
+        assert!(matches!(
+            new(None, "", "12/00/09"),
+            Some(InputError::InvalidScheme(_))
+        ));
+        assert_eq!(new(None, "LR", ""), None);
+        assert_eq!(new(None, "", "12/00/01,21/01/01"), None);
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            InputError::SentinelInQuery(1).to_string(),
+            "The query contains $ at position 1, but $ may only end the string."
+        );
+        assert_eq!(
+            InputError::InvalidChar(b'\n').to_string(),
+            "The string contains byte 0x0a; only printable ASCII characters after $ are supported."
+        );
+        assert_eq!(
+            InputError::UnknownAlgorithm("foo".into()).to_string(),
+            "Unknown algorithm foo."
+        );
+    }
 }
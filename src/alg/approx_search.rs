@@ -0,0 +1,429 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::max;
+
+use crate::{
+    alg::{bwt::BWT, ukkonen::tree_layout, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+const MATCH_COLOUR: Color = (220, 220, 220);
+const MISMATCH_COLOUR: Color = (255, 200, 130);
+const PRUNED_COLOUR: Color = (190, 190, 190);
+const HIT_COLOUR: Color = (150, 200, 255);
+
+/// A node of the search tree: the range of rows starting with a string that matches the last
+/// `depth` characters of the query with `errors` mismatches.
+#[derive(Clone)]
+struct Node {
+    parent: Option<usize>,
+    depth: usize,
+    /// The character prepended on the edge from the parent.
+    c: u8,
+    errors: usize,
+    s: usize,
+    t: usize,
+}
+
+fn mismatches(e: usize) -> String {
+    if e == 1 {
+        "1 mismatch".to_string()
+    } else {
+        format!("{e} mismatches")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum State {
+    Init,
+    // The range of this node is computed from the range of its parent.
+    Extend(usize),
+    Done,
+}
+
+pub struct ApproxSearch {
+    bwt: BWT,
+    /// The maximal number of mismatches.
+    k: usize,
+    /// The nodes in the order of the depth-first search, starting with the root.
+    nodes: Vec<Node>,
+    /// Where each node is drawn, as (column, row).
+    pos: Vec<(usize, usize)>,
+    width: usize,
+    rows: usize,
+    states: Vec<State>,
+}
+
+impl ApproxSearch {
+    pub fn new(s: Vec<u8>, q: Vec<u8>, k: usize) -> Result<Self, InputError> {
+        let bwt = BWT::new(s, q, 1, 1)?;
+        let n = bwt.s.len();
+        let mut nodes = vec![Node {
+            parent: None,
+            depth: 0,
+            c: b'$',
+            errors: 0,
+            s: 0,
+            t: n,
+        }];
+
+        // Try every character in front of the range of `u`, within the mismatch budget.
+        fn search(bwt: &BWT, k: usize, u: usize, nodes: &mut Vec<Node>) {
+            let ql = bwt.q.len();
+            let Node {
+                depth,
+                errors,
+                s,
+                t,
+                ..
+            } = nodes[u];
+            if s == t || depth == ql {
+                return;
+            }
+            let qc = bwt.q[ql - 1 - depth];
+            for (ci, &c) in bwt.alph.iter().enumerate() {
+                let errors = errors + (c != qc) as usize;
+                if errors > k || (c == b'$' && c != qc) {
+                    continue;
+                }
+                let lf = |j: usize| bwt.char_start[ci] + bwt.occ[ci][j] as usize;
+                nodes.push(Node {
+                    parent: Some(u),
+                    depth: depth + 1,
+                    c,
+                    errors,
+                    s: lf(s),
+                    t: lf(t),
+                });
+                search(bwt, k, nodes.len() - 1, nodes);
+            }
+        }
+        search(&bwt, k, 0, &mut nodes);
+
+        // Each node is drawn as its two bounds, after the character on its edge.
+        let pos = tree_layout(nodes.len(), 0, |u| {
+            (0..nodes.len())
+                .filter(|&v| nodes[v].parent == Some(u))
+                .map(|v| (v, 2))
+                .collect()
+        })
+        .into_iter()
+        .map(Option::unwrap)
+        .collect::<Vec<_>>();
+        let width = pos.iter().map(|&(x, _)| x + 2).max().unwrap();
+        let rows = pos.iter().map(|&(_, row)| row + 1).max().unwrap();
+
+        let mut states = vec![State::Init];
+        states.extend((1..nodes.len()).map(State::Extend));
+        states.push(State::Done);
+        Ok(Self {
+            bwt,
+            k,
+            nodes,
+            pos,
+            width,
+            rows,
+            states,
+        })
+    }
+
+    /// The number of nodes visited in the state.
+    fn visited(&self, state: State) -> usize {
+        match state {
+            State::Init => 1,
+            State::Extend(v) => v + 1,
+            State::Done => self.nodes.len(),
+        }
+    }
+
+    /// The positions of the occurrences found by the first `visited` nodes, with their number
+    /// of mismatches, in order.
+    fn hits(&self, visited: usize) -> Vec<(usize, usize)> {
+        let ql = self.bwt.q.len();
+        let mut hits: Vec<_> = self.nodes[..visited]
+            .iter()
+            .filter(|v| v.depth == ql)
+            .flat_map(|v| (v.s..v.t).map(|j| (self.bwt.sa[j], v.errors)))
+            .collect();
+        hits.sort();
+        hits
+    }
+
+    /// The string of the path from the root to `v`, which matches the end of the query.
+    fn path(&self, mut v: usize) -> Vec<u8> {
+        let mut path = vec![];
+        while let Some(u) = self.nodes[v].parent {
+            path.push(self.nodes[v].c);
+            v = u;
+        }
+        path
+    }
+
+    fn explain(&self, state: State) -> Explanation {
+        let q = &self.bwt.q;
+        let ql = q.len();
+        let k = self.k;
+        match state {
+            State::Init => Explanation::new(
+                format!("Search Q with at most {}", mismatches(k)),
+                format!(
+                    "Backward search prepends the characters of Q from the back. To allow \
+                     mismatches, every character is tried at each step, as long as at most {k} \
+                     of them differ from Q. This gives a search tree of ranges [s, t), which is \
+                     explored depth first."
+                ),
+            ),
+            State::Extend(v) => {
+                let node = &self.nodes[v];
+                let u = &self.nodes[node.parent.unwrap()];
+                let i = ql - node.depth;
+                let c = to_label(node.c);
+                let what = if node.c == q[i] {
+                    format!("{c} matches Q[{i}]")
+                } else {
+                    format!(
+                        "{c} replaces Q[{i}] = {}, which is mismatch {} of at most {k}",
+                        to_label(q[i]),
+                        node.errors
+                    )
+                };
+                let result = if node.s == node.t {
+                    "It is empty, so no suffix starts with this string and the branch is pruned."
+                        .to_string()
+                } else if node.depth == ql {
+                    format!(
+                        "All of Q is matched, so every row of the range is an occurrence with \
+                         {}.",
+                        mismatches(node.errors)
+                    )
+                } else if node.errors == k {
+                    "No mismatches are left, so the rest of Q must match exactly.".to_string()
+                } else {
+                    String::new()
+                };
+                Explanation::new(
+                    format!("{c}: [{}, {}) → [{}, {})", u.s, u.t, node.s, node.t),
+                    format!(
+                        "{what}. The new range is [C[{c}] + Occ[{c}][{}], C[{c}] + \
+                         Occ[{c}][{}]) = [{}, {}). {result}",
+                        u.s, u.t, node.s, node.t
+                    )
+                    .trim_end(),
+                )
+            }
+            State::Done => Explanation::new(
+                format!("Hits: {}", self.hits(self.nodes.len()).len()),
+                format!(
+                    "The rows of the complete matches give the positions of the occurrences \
+                     with at most {} through the suffix array. Pruning empty ranges stops \
+                     each branch as soon as no suffix starts with its string.",
+                    mismatches(k)
+                ),
+            ),
+        }
+    }
+}
+
+impl Viz for ApproxSearch {
+    fn canvas_size(&self) -> (usize, usize) {
+        let n = self.bwt.s.len();
+        let sigma = self.bwt.alph.len();
+        let hits = self.hits(self.nodes.len()).len();
+        canvas_size(
+            max(n + sigma + self.width + 5, 24),
+            max(n + 13 + hits, self.rows + 3),
+        )
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![
+            ("match", MATCH_COLOUR),
+            ("mismatch", MISMATCH_COLOUR),
+            ("pruned", PRUNED_COLOUR),
+            ("hit", HIT_COLOUR),
+        ]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = self.states[state];
+        let visited = self.visited(state);
+        let nodes: Vec<Value> = self.nodes[..visited]
+            .iter()
+            .map(|v| {
+                json!({
+                    "parent": v.parent,
+                    "char": v.parent.map(|_| to_label(v.c)),
+                    "errors": v.errors,
+                    "range": (v.s, v.t),
+                })
+            })
+            .collect();
+        let hits: Vec<Value> = self
+            .hits(visited)
+            .into_iter()
+            .map(|(i, errors)| json!({ "pos": i, "errors": errors }))
+            .collect();
+        json!({
+            "state": state,
+            "s": String::from_utf8_lossy(&self.bwt.s),
+            "q": String::from_utf8_lossy(&self.bwt.q),
+            "k": self.k,
+            "sa": self.bwt.sa,
+            "nodes": nodes,
+            "hits": hits,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = self.states[state];
+        let title = self.explain(state).title;
+        let bwt = &self.bwt;
+        let (s, q) = (&bwt.s, &bwt.q);
+        let (n, ql) = (s.len(), q.len());
+        let visited = self.visited(state);
+        let hits = self.hits(visited);
+        let current = match state {
+            State::Extend(v) => Some(v),
+            _ => None,
+        };
+        draw_background(canvas);
+
+        // Positioning
+
+        let ps = Pos(2, 1);
+        // Top left of the sorted suffixes.
+        let psa = Pos(2, 4);
+        let cj = psa.left(2);
+        let csa = psa.left(1);
+        let pocc = psa.right(n + 2);
+        let pc = pocc.up(1);
+        // The root of the tree, right of the table.
+        let pt = Pos(pocc.0 + bwt.alph.len() + 1, 1);
+        let at = |v: usize| {
+            let (x, row) = self.pos[v];
+            pt.right(x).down(row)
+        };
+        let pq = psa.down(n + 2);
+        let ppath = pq.down(1);
+        let phits = ppath.down(2);
+        let all_hits = self.hits(self.nodes.len()).len();
+        let plabel = Pos(0, max(phits.1 + all_hits + 2, self.rows + 1));
+
+        let hit_colour = |i: usize| {
+            if hits.iter().any(|&(j, _)| j <= i && i < j + ql) {
+                HIT_COLOUR
+            } else {
+                WHITE
+            }
+        };
+        draw_string_with_labels(ps, s, hit_colour, canvas);
+
+        // The table.
+        draw_label(cj.up(1), "j", canvas);
+        draw_label(csa.up(1), "A", canvas);
+        for (j, &i) in bwt.sa.iter().enumerate() {
+            draw_label(cj.down(j), &j.to_string(), canvas);
+            draw_label(csa.down(j), &i.to_string(), canvas);
+            draw_string(psa.down(j), &s[i..], |_| WHITE, canvas);
+        }
+        draw_label(pc.left(1).up(1), "σ", canvas);
+        draw_label(pc.left(1), "C", canvas);
+        for (ci, &c) in bwt.alph.iter().enumerate() {
+            draw_label(pc.right(ci).up(1), &to_label(c), canvas);
+            draw_label(pc.right(ci), &bwt.char_start[ci].to_string(), canvas);
+            for j in 0..=n {
+                draw_label(pocc.right(ci).down(j), &bwt.occ[ci][j].to_string(), canvas);
+            }
+        }
+
+        // The search tree.
+        let char_colour = |v: usize| {
+            let node = &self.nodes[v];
+            if node.c == q[ql - node.depth] {
+                MATCH_COLOUR
+            } else {
+                MISMATCH_COLOUR
+            }
+        };
+        for (v, node) in self.nodes[..visited].iter().enumerate() {
+            let p = at(v);
+            if let Some(u) = node.parent {
+                draw_char_box(p.left(1), node.c, char_colour(v), canvas);
+                if self.pos[u].1 != self.pos[v].1 {
+                    draw_edge(at(u), p.left(1), BLACK, canvas);
+                }
+            }
+            let colour = if node.s == node.t {
+                PRUNED_COLOUR
+            } else if node.depth == ql {
+                HIT_COLOUR
+            } else {
+                WHITE
+            };
+            draw_node(p, &node.s.to_string(), colour, canvas);
+            draw_node(p.right(1), &node.t.to_string(), colour, canvas);
+        }
+
+        // The query, and the string of the current node below it.
+        draw_label(pq.left(1), "Q", canvas);
+        draw_string(pq, q, |_| WHITE, canvas);
+        if let Some(v) = current {
+            let node = &self.nodes[v];
+            let u = node.parent.unwrap();
+            let parent = &self.nodes[u];
+            let ci = bwt.alph.binary_search(&node.c).unwrap();
+            let start = ql - node.depth;
+            let mut w = v;
+            for (k, &c) in self.path(v).iter().enumerate() {
+                draw_char_box(ppath.right(start + k), c, char_colour(w), canvas);
+                w = self.nodes[w].parent.unwrap();
+            }
+            draw_highlight(pq.right(start), BLUE, canvas);
+
+            draw_highlight_box(at(u), 2, 1, RED, canvas);
+            draw_highlight_box(at(v), 2, 1, BLUE, canvas);
+            draw_highlight_box(
+                psa.down(parent.s),
+                max(parent.depth, 1),
+                parent.t - parent.s,
+                RED,
+                canvas,
+            );
+            draw_highlight_box(psa.down(node.s), node.depth, node.t - node.s, BLUE, canvas);
+            draw_highlight(pc.right(ci), BLUE, canvas);
+            draw_highlight(pocc.right(ci).down(parent.s), BLUE, canvas);
+            draw_highlight(pocc.right(ci).down(parent.t), BLUE, canvas);
+            if node.depth == ql {
+                for j in node.s..node.t {
+                    draw_highlight_box(ps.right(bwt.sa[j]), ql, 1, BLUE, canvas);
+                }
+            }
+        }
+
+        // The hits found so far.
+        if !hits.is_empty() {
+            draw_text(phits.left(2), "hits:", canvas);
+        }
+        for (h, &(i, errors)) in hits.iter().enumerate() {
+            draw_text(
+                phits.down(h + 1).left(2),
+                &format!(
+                    "{i}: {} with {}",
+                    String::from_utf8_lossy(&s[i..i + ql]),
+                    mismatches(errors)
+                ),
+                canvas,
+            );
+        }
+        draw_text(plabel, &title, canvas);
+        true
+    }
+}
@@ -1,8 +1,13 @@
-use crate::{alg::Viz, canvas::*};
+use crate::{
+    alg::{char_map, validate_query, validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
 use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
 use std::{cmp::max, ops::Range};
 
-#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy)]
+#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Serialize)]
 pub enum QueryStep {
     PreviousDone,
     HighlightChar,
@@ -20,7 +25,7 @@ pub enum QueryStep {
 }
 use QueryStep::*;
 
-#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy)]
+#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Serialize)]
 pub enum State {
     Init,
     // Bool: put last column before first?
@@ -35,9 +40,125 @@ pub enum State {
     Equivalence(usize),
     Pause,
     Query(usize, QueryStep),
+    // Search schemes: the start of the given search, with its root node.
+    Search(usize),
+    // The given node is reached by extending its parent.
+    Extend(usize),
+    // Return to the given node after a branch is done.
+    Backtrack(usize),
+    Hits,
 }
 use State::*;
 
+/// The side on which the matched part of the query is extended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+/// Parse the order of the extensions of an exact query, written as `L` and `R`, e.g. `LRRLR`.
+pub fn parse_order(order: &str) -> Result<Vec<Direction>, InputError> {
+    order
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'L' => Ok(Direction::Left),
+            'R' => Ok(Direction::Right),
+            _ => Err(InputError::InvalidOrder(format!(
+                "{order:?} may only contain L and R."
+            ))),
+        })
+        .collect()
+}
+
+/// One search of a search scheme: the order in which the parts of the query are matched, and
+/// the minimal and maximal number of mismatches after each of them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Search {
+    pub order: Vec<usize>,
+    pub lower: Vec<usize>,
+    pub upper: Vec<usize>,
+}
+
+/// Parse a search scheme written as `order/lower/upper` for each search, separated by commas,
+/// with the parts numbered from 1, e.g. `12/00/01,21/01/01`.
+pub fn parse_scheme(scheme: &str) -> Result<Vec<Search>, InputError> {
+    let err = |reason: &str| InputError::InvalidScheme(format!("{scheme:?} {reason}"));
+    let digits = |text: &str| {
+        text.bytes()
+            .map(|c| c.is_ascii_digit().then(|| (c - b'0') as usize))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| err("may only contain digits, '/' and ','."))
+    };
+    let searches = scheme
+        .split(',')
+        .map(|search| {
+            let fields: Vec<&str> = search.trim().split('/').collect();
+            let [order, lower, upper] = fields[..] else {
+                return Err(err("needs order/lower/upper for each search."));
+            };
+            Ok(Search {
+                order: digits(order)?
+                    .into_iter()
+                    .map(|p| p.wrapping_sub(1))
+                    .collect(),
+                lower: digits(lower)?,
+                upper: digits(upper)?,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let parts = searches[0].order.len();
+    for search in &searches {
+        let Search {
+            order,
+            lower,
+            upper,
+        } = search;
+        if order.len() != parts || lower.len() != parts || upper.len() != parts {
+            return Err(err("must have the same number of parts everywhere."));
+        }
+        if parts == 0 || order.iter().copied().sorted().ne(0..parts) {
+            return Err(err("must order each of the parts 1 to p exactly once."));
+        }
+        // Bidirectional search can only extend the matched parts on the left or right.
+        let (mut lo, mut hi) = (order[0], order[0]);
+        for &part in &order[1..] {
+            if part + 1 == lo {
+                lo = part;
+            } else if part == hi + 1 {
+                hi = part;
+            } else {
+                return Err(err("must extend the matched parts to the left or right."));
+            }
+        }
+        let sorted = |v: &[usize]| v.windows(2).all(|w| w[0] <= w[1]);
+        if !sorted(lower) || !sorted(upper) || lower.iter().zip(upper).any(|(l, u)| l > u) {
+            return Err(err("needs increasing bounds with lower <= upper."));
+        }
+    }
+    Ok(searches)
+}
+
+/// Numbers below 10 written next to each other, as in the notation of search schemes.
+fn digits(v: impl IntoIterator<Item = usize>) -> String {
+    v.into_iter().map(|x| x.to_string()).join("")
+}
+
+/// A node of the search tree of a search scheme.
+#[derive(Clone, Serialize)]
+struct Node {
+    search: usize,
+    parent: Option<usize>,
+    /// The matched positions of the query.
+    matched: Range<usize>,
+    /// The string matched there, which differs from the query in `errors` positions.
+    text: Vec<u8>,
+    errors: usize,
+    range: Range<usize>,
+    range_r: Range<usize>,
+}
+
 fn s_stats(s: &[u8]) -> (usize, usize) {
     let mut cnts = [0; 256];
     for &c in s {
@@ -48,10 +169,14 @@ fn s_stats(s: &[u8]) -> (usize, usize) {
     (num_chars, max_char_cnt)
 }
 
+/// Longer queries do not fit left of the forward suffix array.
+const MAX_QUERY_LEN: usize = 10;
+
 const DEFAULT: Color = (240, 240, 240);
 const HIGHLIGHT: Color = GREEN;
 const SOFT_HIGHLIGHT: Color = (180, 250, 180);
 const NEXT_CHAR: Color = (180, 180, 250);
+const MISMATCH: Color = (255, 200, 130);
 
 fn to_c(condition: bool) -> Color {
     if condition {
@@ -72,6 +197,14 @@ pub struct BiBWT {
     occ: Vec<Vec<usize>>,
     sa_r: Vec<usize>,
     occ_r: Vec<Vec<usize>>,
+    /// The position of the query where exact matching starts.
+    start: usize,
+    /// The side of each extension after the first character.
+    order: Vec<Direction>,
+    /// The searches of the search scheme; empty for exact matching.
+    searches: Vec<Search>,
+    /// The search trees of all searches, each starting with its root.
+    nodes: Vec<Node>,
 
     pub states: Vec<State>,
 }
@@ -81,7 +214,72 @@ fn rev(s: &[u8]) -> Vec<u8> {
 }
 
 impl BiBWT {
-    pub fn new(s: Vec<u8>, q: Vec<u8>) -> Self {
+    /// Exact matching starts at position `start` of the query and extends it as given by `order`.
+    /// By default it starts in the middle, and first extends to the left and then to the right.
+    /// A search scheme has its own orders, so it can't be combined with `start` or `order`.
+    pub fn new(
+        s: Vec<u8>,
+        q: Vec<u8>,
+        start: Option<usize>,
+        order: Vec<Direction>,
+        scheme: Vec<Search>,
+    ) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        validate_query(&s, &q, s.len().min(MAX_QUERY_LEN))?;
+        if !scheme.is_empty() && (start.is_some() || !order.is_empty()) {
+            return Err(InputError::InvalidOrder(
+                "can't be combined with a search scheme, which has its own orders.".into(),
+            ));
+        }
+        let ql = q.len();
+        let lefts = order.iter().filter(|&&d| d == Direction::Left).count();
+        let (start, order) = match (start, order.is_empty()) {
+            (start, true) => {
+                let start = start.unwrap_or(ql.div_ceil(2).saturating_sub(1));
+                if ql > 0 && start >= ql {
+                    return Err(InputError::InvalidOrder(format!(
+                        "starts at {start}, but the query has length {ql}."
+                    )));
+                }
+                let mut order = vec![Direction::Left; start];
+                order.resize(ql.saturating_sub(1), Direction::Right);
+                (start, order)
+            }
+            (start, false) => {
+                if order.len() + 1 != ql {
+                    return Err(InputError::InvalidOrder(format!(
+                        "has {} extensions, but the query needs {}.",
+                        order.len(),
+                        ql.saturating_sub(1)
+                    )));
+                }
+                if start.is_some_and(|start| start != lefts) {
+                    return Err(InputError::InvalidOrder(format!(
+                        "has {lefts} extensions to the left, so it must start at position {lefts}."
+                    )));
+                }
+                (lefts, order)
+            }
+        };
+        if let Some(search) = scheme.first() {
+            if search.order.len() > q.len() {
+                return Err(InputError::InvalidScheme(format!(
+                    "has {} parts, but the query has length {}.",
+                    search.order.len(),
+                    q.len()
+                )));
+            }
+        }
+        // More mismatches than characters can never be reached, and would make the search
+        // enumerate every string of the length of the query.
+        if let Some(&max) = scheme.iter().filter_map(|search| search.upper.last()).max() {
+            if max > q.len() {
+                return Err(InputError::InvalidScheme(format!(
+                    "allows {max} mismatches, but the query has length {}.",
+                    q.len()
+                )));
+            }
+        }
         let n = s.len();
         let alph = {
             let mut alph = s.to_vec();
@@ -156,8 +354,10 @@ impl BiBWT {
         }
 
         states.push(Pause);
-        let ql = q.len();
         for i in 1..ql {
+            if !scheme.is_empty() {
+                break;
+            }
             for qs in [
                 PreviousDone,
                 HighlightChar,
@@ -176,9 +376,11 @@ impl BiBWT {
                 states.push(Query(i, qs));
             }
         }
-        states.push(Query(ql, PreviousDone));
+        if scheme.is_empty() {
+            states.push(Query(ql, PreviousDone));
+        }
 
-        BiBWT {
+        let mut alg = BiBWT {
             s,
             q,
             s2,
@@ -189,10 +391,185 @@ impl BiBWT {
             occ,
             sa_r,
             occ_r,
+            start,
+            order,
+            searches: scheme,
+            nodes: vec![],
             states,
+        };
+        if !alg.searches.is_empty() {
+            let mut nodes = vec![];
+            for si in 0..alg.searches.len() {
+                let steps = alg.search_steps(&alg.searches[si]);
+                let pos = steps[0].0;
+                let start = if steps.len() > 1 && steps[1].0 < pos {
+                    pos + 1
+                } else {
+                    pos
+                };
+                let root = nodes.len();
+                nodes.push(Node {
+                    search: si,
+                    parent: None,
+                    matched: start..start,
+                    text: vec![],
+                    errors: 0,
+                    range: 0..n,
+                    range_r: 0..n,
+                });
+                alg.search_tree(&steps, root, &mut nodes);
+
+                alg.states.push(Search(si));
+                for (v, node) in nodes.iter().enumerate().skip(root + 1) {
+                    let u = node.parent.unwrap();
+                    if u != v - 1 {
+                        alg.states.push(Backtrack(u));
+                    }
+                    alg.states.push(Extend(v));
+                }
+            }
+            alg.states.push(Hits);
+            alg.nodes = nodes;
+        }
+        Ok(alg)
+    }
+
+    /// The positions of the query in the order in which the search matches them, with the index
+    /// of their part in the order. The first part is matched towards the second one.
+    fn search_steps(&self, search: &Search) -> Vec<(usize, usize)> {
+        let ql = self.q.len();
+        let parts = search.order.len();
+        let first = search.order[0];
+        let left_first = search.order.get(1).is_none_or(|&next| next < first);
+        let mut steps = vec![];
+        for (i, &part) in search.order.iter().enumerate() {
+            let positions = part * ql / parts..(part + 1) * ql / parts;
+            if part < first || (i == 0 && left_first) {
+                steps.extend(positions.rev().map(|pos| (pos, i)));
+            } else {
+                steps.extend(positions.map(|pos| (pos, i)));
+            }
+        }
+        steps
+    }
+
+    /// The ranges of the string of `range` and `range_r` after extending it with `c`.
+    fn extend(
+        &self,
+        range: &Range<usize>,
+        range_r: &Range<usize>,
+        c: u8,
+        left: bool,
+    ) -> (Range<usize>, Range<usize>) {
+        let ci = self.alph.binary_search(&c).unwrap();
+        // The array that is extended like a normal BWT, and the one that follows.
+        let (first, second, occ) = if left {
+            (range, range_r, &self.occ)
+        } else {
+            (range_r, range, &self.occ_r)
+        };
+        let start = self.char_start[ci] + occ[ci][first.start];
+        let end = self.char_start[ci] + occ[ci][first.end];
+        let smaller: usize = (0..ci)
+            .map(|cj| occ[cj][first.end] - occ[cj][first.start])
+            .sum();
+        let other = second.start + smaller..second.start + smaller + end - start;
+        if left {
+            (start..end, other)
+        } else {
+            (other, start..end)
         }
     }
 
+    /// Depth-first search below node `u`, trying every character within the error bounds.
+    fn search_tree(&self, steps: &[(usize, usize)], u: usize, nodes: &mut Vec<Node>) {
+        let node = nodes[u].clone();
+        let depth = node.matched.len();
+        if node.range.is_empty() || depth == steps.len() {
+            return;
+        }
+        let search = &self.searches[node.search];
+        let (pos, i) = steps[depth];
+        let last = steps.get(depth + 1).is_none_or(|&(_, next)| next != i);
+        let left = pos < node.matched.start;
+        for &c in &self.alph {
+            let errors = node.errors + (c != self.q[pos]) as usize;
+            if (c == b'$' && c != self.q[pos])
+                || errors > search.upper[i]
+                || (last && errors < search.lower[i])
+            {
+                continue;
+            }
+            let (range, range_r) = self.extend(&node.range, &node.range_r, c, left);
+            let (matched, text) = if left {
+                let text = [&[c], &node.text[..]].concat();
+                (pos..node.matched.end, text)
+            } else {
+                let text = [&node.text[..], &[c]].concat();
+                (node.matched.start..pos + 1, text)
+            };
+            nodes.push(Node {
+                parent: Some(u),
+                matched,
+                text,
+                errors,
+                range,
+                range_r,
+                ..node
+            });
+            self.search_tree(steps, nodes.len() - 1, nodes);
+        }
+    }
+
+    /// The start of each part of the query, and the end of the last one.
+    fn part_bounds(&self) -> Vec<usize> {
+        let ql = self.q.len();
+        let parts = self.searches[0].order.len();
+        (0..=parts).map(|part| part * ql / parts).collect()
+    }
+
+    /// The node of the search tree shown in the state, if any.
+    fn scheme_node(&self, state: State) -> Option<usize> {
+        match state {
+            Search(si) => self.nodes.iter().position(|v| v.search == si),
+            Extend(v) | Backtrack(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The positions of the occurrences with their least number of mismatches, in order.
+    fn hits(&self) -> Vec<(usize, usize)> {
+        let mut hits = self
+            .nodes
+            .iter()
+            .filter(|v| v.matched.len() == self.q.len())
+            .flat_map(|v| v.range.clone().map(|j| (self.sa[j], v.errors)))
+            .sorted()
+            .collect_vec();
+        hits.dedup_by_key(|&mut (i, _)| i);
+        hits
+    }
+
+    /// The part of the query matched after `step` steps: the start character, extended on the
+    /// sides given by the order.
+    fn matched(&self, step: usize) -> Range<usize> {
+        if step == 0 {
+            return self.start..self.start;
+        }
+        let lefts = self.order[..step - 1]
+            .iter()
+            .filter(|&&d| d == Direction::Left)
+            .count();
+        self.start - lefts..self.start + step - lefts
+    }
+
+    /// Whether the query is extended to the left after `step` steps, or `None` when it is
+    /// fully matched.
+    fn extends_left(&self, step: usize) -> Option<bool> {
+        let dir = self.order.get(step.checked_sub(1)?)?;
+        Some(*dir == Direction::Left)
+    }
+
     fn query_ranges(&self, q: &[u8]) -> (Range<usize>, Range<usize>) {
         let n = self.s.len();
         let j_begin = (0..n)
@@ -213,6 +590,304 @@ impl BiBWT {
             .1;
         ((j_begin..j_end), (j_begin_r..j_end_r))
     }
+
+    fn explain(&self, state: State) -> Explanation {
+        match state {
+            Init => Explanation::new("Input string S.", "The input string S, ending in $."),
+            LeftSA(0) => Explanation::new(
+                "Forward suffix array",
+                "The sorted rotations of S, as for the normal BWT.",
+            ),
+            LeftSA(1) => Explanation::new(
+                "Move last column before first",
+                "The last column L is the character before each suffix.",
+            ),
+            LeftSA(_) => Explanation::new(
+                "Only show prefixes of the array",
+                "Only the first characters of each suffix matter for querying.",
+            ),
+            RightSA(0) => Explanation::new(
+                "Reverse suffix array",
+                "The rotations of S sorted by reading them from right to left: \
+                 the suffix array of the reversed string.",
+            ),
+            RightSA(1) => Explanation::new(
+                "Move last column after first",
+                "The last column Lr is the character after each reversed suffix.",
+            ),
+            RightSA(_) => Explanation::new(
+                "Only show suffixes of the array",
+                "Only the last characters of each prefix matter for querying.",
+            ),
+            BothSA => Explanation::new(
+                "Forward & Reverse suffix array",
+                "A range in the forward array and a range in the reverse array \
+                 can both represent the same substring of S.",
+            ),
+            CharCounts => Explanation::new(
+                "Count number of smaller characters for each c",
+                "C[c] is the first row of F and of Fr starting with c.",
+            ),
+            LeftOcc => Explanation::new(
+                "Count occurrences for L",
+                "Occ[c][j] is the number of times c occurs in L above row j.",
+            ),
+            RightOcc => Explanation::new(
+                "Count occurrences for Lr",
+                "Occr[c][j] is the number of times c occurs in Lr above row j.",
+            ),
+            Equivalence(c) => Explanation::new(
+                "Sets of chars before/after c are the same.",
+                format!(
+                    "The characters before {0} in the forward array are the characters after {0} \
+                     in the reverse array, and vice versa.",
+                    to_label(self.alph[c])
+                ),
+            ),
+            Pause => Explanation::new(
+                "Ready for querying",
+                "Both arrays, C and both Occ tables are ready.",
+            ),
+            Query(step, qs) => {
+                let q = &self.q;
+                let start = self.start;
+                if qs == PreviousDone {
+                    return if step == 1 {
+                        Explanation::new(
+                            "Start with the range of the first character",
+                            format!(
+                                "Start at position {start} of Q, with the range of {} in both \
+                                 arrays.",
+                                to_label(q[start])
+                            ),
+                        )
+                    } else {
+                        Explanation::new(
+                            "Matching done",
+                            format!(
+                                "Both ranges now represent the {step} matched characters of Q."
+                            ),
+                        )
+                    };
+                }
+
+                let Some(extend_left) = self.extends_left(step) else {
+                    unreachable!()
+                };
+                let done = self.matched(step);
+                let c = to_label(
+                    q[if extend_left {
+                        done.start - 1
+                    } else {
+                        done.end
+                    }],
+                );
+                // The array that is extended like a normal BWT, and the one that follows.
+                let (first, second) = if extend_left {
+                    ("Fwd", "Rev")
+                } else {
+                    ("Rev", "Fwd")
+                };
+                let r = if extend_left { "" } else { "r" };
+                let (dir, short) = if extend_left {
+                    ("forward", "fwd")
+                } else {
+                    ("reverse", "rev")
+                };
+                match qs {
+                    PreviousDone => unreachable!(),
+                    HighlightChar => Explanation::new(
+                        if extend_left {
+                            "Extend query on the left"
+                        } else {
+                            "Extend query on the right"
+                        },
+                        format!("The next character to match is {c}."),
+                    ),
+                    HighlightMatches => Explanation::new(
+                        "Matches for next char",
+                        format!("The rows in both ranges that continue with {c}."),
+                    ),
+                    EquivalenceFirst => Explanation::new(
+                        format!("{first}: matches in L{r} correspond to matches in F{r}"),
+                        format!(
+                            "The occurrences of {c} in L{r} within the range map to a \
+                             contiguous range of rows starting with {c} in F{r}."
+                        ),
+                    ),
+                    CountFirst => Explanation::new(
+                        format!("{first}: positions of matches"),
+                        format!("Occ{r}[{c}] at the start and end of the range count the {c}s above and in it."),
+                    ),
+                    SmallerCountFirst => Explanation::new(
+                        format!("{first}: number of smaller chars"),
+                        format!("C[{c}] is where the rows starting with {c} begin."),
+                    ),
+                    ExtendFirst => Explanation::new(
+                        format!("{first}: #smaller + match-positions"),
+                        format!("The new range is C[{c}] + Occ{r}[{c}] at both ends of the old range."),
+                    ),
+                    SmallerWindowSecond => Explanation::new(
+                        format!("{second}: range shrinks; skip chars < c"),
+                        format!(
+                            "In the other array, the new range is a part of the old range. \
+                             Rows extended with a character smaller than {c} come first and are skipped."
+                        ),
+                    ),
+                    EquivalenceSecond => Explanation::new(
+                        format!("{second}: equal to #{{<c}} in {dir} range"),
+                        format!(
+                            "The number of skipped rows equals the number of characters smaller \
+                             than {c} in the {dir} range."
+                        ),
+                    ),
+                    CountSecond => Explanation::new(
+                        format!("{second}: Char counts before/after {short} range"),
+                        "Occ at the start and end of the range for each smaller character.",
+                    ),
+                    ComputeSecond => Explanation::new(
+                        format!("{second}: Total count #{{<c}} in {dir} range"),
+                        format!("Sum the differences over all characters smaller than {c}."),
+                    ),
+                    ExtendStartSecond => Explanation::new(
+                        "Add #{<c} to current start",
+                        "The new start skips the rows extended by smaller characters.",
+                    ),
+                    ExtendEndSecond => Explanation::new(
+                        "Add #{≤c} to current start",
+                        format!("The new end also includes the rows extended by {c}."),
+                    ),
+                }
+            }
+            Search(si) => {
+                let search = &self.searches[si];
+                Explanation::new(
+                    format!("Search {} of {}", si + 1, self.searches.len()),
+                    format!(
+                        "The query is split into {} parts, which this search matches in the \
+                         order {}. After the i-th of them, the number of mismatches must be at \
+                         least L[i] and at most U[i], with L = {} and U = {}. It starts from \
+                         the full ranges.",
+                        search.order.len(),
+                        digits(search.order.iter().map(|&part| part + 1)),
+                        digits(search.lower.iter().copied()),
+                        digits(search.upper.iter().copied()),
+                    ),
+                )
+            }
+            Extend(v) => {
+                let node = &self.nodes[v];
+                let u = &self.nodes[node.parent.unwrap()];
+                let left = node.matched.start < u.matched.start;
+                let pos = if left {
+                    node.matched.start
+                } else {
+                    node.matched.end - 1
+                };
+                let c = node.text[pos - node.matched.start];
+                let part = self.part_bounds().partition_point(|&b| b <= pos);
+                let search = &self.searches[node.search];
+                let i = search.order.iter().position(|&p| p + 1 == part).unwrap();
+                let what = if c == self.q[pos] {
+                    format!("{} matches Q[{pos}]", to_label(c))
+                } else {
+                    format!(
+                        "{} replaces Q[{pos}] = {}, mismatch {}",
+                        to_label(c),
+                        to_label(self.q[pos]),
+                        node.errors
+                    )
+                };
+                let (first, second) = if left {
+                    ("forward", "reverse")
+                } else {
+                    ("reverse", "forward")
+                };
+                let result = if node.range.is_empty() {
+                    "Both ranges are empty, so this branch fails and the search backtracks."
+                        .to_string()
+                } else if node.matched.len() == self.q.len() {
+                    format!(
+                        "All parts are matched, so the rows of the forward range are \
+                         occurrences with {} mismatches.",
+                        node.errors
+                    )
+                } else {
+                    String::new()
+                };
+                Explanation::new(
+                    format!(
+                        "Extend {} with {}",
+                        if left { "left" } else { "right" },
+                        to_label(c)
+                    ),
+                    format!(
+                        "{what}. After part {part}, {} to {} mismatches are allowed in total. The \
+                         {first} range [{}, {}) becomes [{}, {}) using C and Occ, and the \
+                         {second} range [{}, {}) shrinks to [{}, {}). {result}",
+                        search.lower[i],
+                        search.upper[i],
+                        if left { u.range.start } else { u.range_r.start },
+                        if left { u.range.end } else { u.range_r.end },
+                        if left {
+                            node.range.start
+                        } else {
+                            node.range_r.start
+                        },
+                        if left {
+                            node.range.end
+                        } else {
+                            node.range_r.end
+                        },
+                        if left { u.range_r.start } else { u.range.start },
+                        if left { u.range_r.end } else { u.range.end },
+                        if left {
+                            node.range_r.start
+                        } else {
+                            node.range.start
+                        },
+                        if left {
+                            node.range_r.end
+                        } else {
+                            node.range.end
+                        },
+                    )
+                    .trim_end(),
+                )
+            }
+            Backtrack(u) => {
+                let node = &self.nodes[u];
+                let Range { start, end } = node.matched;
+                let what = if node.parent.is_none() {
+                    "the full ranges".to_string()
+                } else {
+                    format!(
+                        "the ranges of {}, which matches positions {start} to {} of Q with {} \
+                         mismatches",
+                        String::from_utf8_lossy(&node.text),
+                        end - 1,
+                        node.errors
+                    )
+                };
+                Explanation::new(
+                    format!(
+                        "Backtrack to [{}, {}) and [{}, {})",
+                        node.range.start, node.range.end, node.range_r.start, node.range_r.end
+                    ),
+                    format!(
+                        "The branch is done, so the search returns to {what}, and tries the next \
+                         character."
+                    ),
+                )
+            }
+            Hits => Explanation::new(
+                format!("Hits: {}", self.hits().len()),
+                "The occurrences of all searches together, each found by at least one of them. \
+                 A search scheme is complete when every possible distribution of the mismatches \
+                 over the parts is covered by one of its searches.",
+            ),
+        }
+    }
 }
 
 impl Viz for BiBWT {
@@ -227,8 +902,118 @@ impl Viz for BiBWT {
         self.states.len()
     }
 
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![
+            ("default", DEFAULT),
+            ("highlight", HIGHLIGHT),
+            ("softhighlight", SOFT_HIGHLIGHT),
+            ("nextchar", NEXT_CHAR),
+        ]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = self.states[state];
+        let n = self.s.len();
+        let mut trace = Map::new();
+        trace.insert("state".into(), json!(state));
+        trace.insert("s".into(), json!(String::from_utf8_lossy(&self.s)));
+        let right_only = matches!(state, RightSA(_));
+        if state >= LeftSA(0) && !right_only {
+            trace.insert("sa".into(), json!(self.sa));
+        }
+        if state >= LeftSA(1) && !right_only {
+            let bwt = self.sa.iter().map(|&i| self.s2[i + n - 1]).collect_vec();
+            trace.insert("bwt".into(), json!(String::from_utf8_lossy(&bwt)));
+        }
+        if state >= RightSA(0) {
+            trace.insert("sa_r".into(), json!(self.sa_r));
+        }
+        if state >= RightSA(1) {
+            let bwt_r = self.sa_r.iter().map(|&i| self.s2[i + n]).collect_vec();
+            trace.insert("bwt_r".into(), json!(String::from_utf8_lossy(&bwt_r)));
+        }
+        if state >= CharCounts {
+            trace.insert("c".into(), json!(char_map(&self.alph, &self.char_start)));
+        }
+        if state >= LeftOcc {
+            trace.insert("occ".into(), json!(char_map(&self.alph, &self.occ)));
+        }
+        if state >= RightOcc {
+            trace.insert("occ_r".into(), json!(char_map(&self.alph, &self.occ_r)));
+        }
+
+        let mut highlight = Value::Null;
+        if let Equivalence(ci) = state {
+            let start = self.char_start[ci];
+            highlight = json!({
+                "char": to_label(self.alph[ci]),
+                "rows": [start, start + self.char_count[ci]],
+            });
+        }
+        if let Query(step, qs) = state {
+            let q = &self.q;
+            let done = self.matched(step);
+            let (range, range_r) = self.query_ranges(&q[done.clone()]);
+            trace.insert("q".into(), json!(String::from_utf8_lossy(q)));
+            trace.insert("matched".into(), json!([done.start, done.end]));
+            trace.insert("range".into(), json!([range.start, range.end]));
+            trace.insert("range_r".into(), json!([range_r.start, range_r.end]));
+
+            if let Some(extend_left) = self.extends_left(step).filter(|_| qs != PreviousDone) {
+                let extend_idx = if extend_left {
+                    done.start - 1
+                } else {
+                    done.end
+                };
+                let next = if extend_left {
+                    q[extend_idx..done.end].to_vec()
+                } else {
+                    q[done.start..=extend_idx].to_vec()
+                };
+                let (next_range, next_range_r) = self.query_ranges(&next);
+                highlight = json!({
+                    "char": to_label(q[extend_idx]),
+                    "extend": if extend_left { "left" } else { "right" },
+                    "next_range": [next_range.start, next_range.end],
+                    "next_range_r": [next_range_r.start, next_range_r.end],
+                });
+            }
+        }
+        if let Some(v) = self.scheme_node(state) {
+            let node = &self.nodes[v];
+            trace.insert("q".into(), json!(String::from_utf8_lossy(&self.q)));
+            trace.insert("search".into(), json!(self.searches[node.search]));
+            trace.insert(
+                "matched".into(),
+                json!([node.matched.start, node.matched.end]),
+            );
+            trace.insert("text".into(), json!(String::from_utf8_lossy(&node.text)));
+            trace.insert("errors".into(), json!(node.errors));
+            trace.insert("range".into(), json!([node.range.start, node.range.end]));
+            trace.insert(
+                "range_r".into(),
+                json!([node.range_r.start, node.range_r.end]),
+            );
+        }
+        if state == Hits {
+            let hits = self
+                .hits()
+                .into_iter()
+                .map(|(i, errors)| json!({ "pos": i, "errors": errors }))
+                .collect_vec();
+            trace.insert("hits".into(), json!(hits));
+        }
+        trace.insert("highlight".into(), highlight);
+        Value::Object(trace)
+    }
+
     fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
         let state = self.states[state];
+        let title = self.explain(state).title;
         draw_background(canvas);
 
         let s = &self.s;
@@ -291,7 +1076,7 @@ impl Viz for BiBWT {
         draw_string_with_labels(ps, &s, |i| to_c(s[i] == '$' as u8), canvas);
 
         if state == Init {
-            draw_text(plabel, "Input string S.", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -328,7 +1113,7 @@ impl Viz for BiBWT {
             }
 
             if state == LeftSA(0) {
-                draw_text(plabel, "Forward suffix array", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -339,11 +1124,11 @@ impl Viz for BiBWT {
                 draw_char_box(plast.down(j), self.s2[i + n - 1], SOFT_HIGHLIGHT, canvas);
             }
             if state == LeftSA(1) {
-                draw_text(plabel, "Move last column before first", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
             if state == LeftSA(2) {
-                draw_text(plabel, "Only show prefixes of the array", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         }
@@ -380,7 +1165,7 @@ impl Viz for BiBWT {
             }
 
             if state == RightSA(0) {
-                draw_text(plabel, "Reverse suffix array", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -391,16 +1176,16 @@ impl Viz for BiBWT {
                 draw_char_box(plast_r.down(j), self.s2[i + n], SOFT_HIGHLIGHT, canvas);
             }
             if state == RightSA(1) {
-                draw_text(plabel, "Move last column after first", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
             if state == RightSA(2) {
-                draw_text(plabel, "Only show suffixes of the array", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         }
         if state == BothSA {
-            draw_text(plabel, "Forward & Reverse suffix array", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -432,11 +1217,7 @@ impl Viz for BiBWT {
                 draw_highlight_box(pfirst, 1, n, RED, canvas);
                 draw_highlight_box(pfirst_r, 1, n, RED, canvas);
                 draw_highlight_box(rsigma, self.alph.len(), 2, RED, canvas);
-                draw_text(
-                    plabel,
-                    "Count number of smaller characters for each c",
-                    canvas,
-                );
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         }
@@ -453,7 +1234,7 @@ impl Viz for BiBWT {
             if state == LeftOcc {
                 draw_highlight_box(pocc, self.alph.len(), n + 1, BLUE, canvas);
                 draw_highlight_box(plast, 1, n, BLUE, canvas);
-                draw_text(plabel, "Count occurrences for L", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -471,7 +1252,7 @@ impl Viz for BiBWT {
             if state == RightOcc {
                 draw_highlight_box(pocc_r, self.alph.len(), n + 1, BLUE, canvas);
                 draw_highlight_box(plast_r, 1, n, BLUE, canvas);
-                draw_text(plabel, "Count occurrences for Lr", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         };
@@ -485,37 +1266,37 @@ impl Viz for BiBWT {
             draw_highlight_box(plast_r.down(s), 1, l, BLUE, canvas);
             draw_highlight_box(pfirst.right(1).down(s), 1, l, BLUE, canvas);
 
-            draw_text(plabel, "Sets of chars before/after c are the same.", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
         if state == Pause {
-            draw_text(plabel, "Ready for querying", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
         // Draw query
-        // We start in the middle, with the first char already matched.
-        // Then extend left to the start.
-        // Then extend right to the end.
-
-        if let Query(step, qs) = state {
-            // Current state
+        // We start with the first char already matched.
+        // Then extend left or right in the given order.
+
+        // The matched part `done` of the query as `text`, with its ranges in both arrays.
+        let draw_match = |done: &Range<usize>,
+                          text: &[u8],
+                          range: &Range<usize>,
+                          range_r: &Range<usize>,
+                          canvas: &mut CanvasBox| {
             let q = &self.q;
-            let ql = q.len();
-            let mid = (ql + 1) / 2;
-
-            let done = if step <= mid {
-                mid - step..mid
-            } else {
-                0..step
-            };
-            let (range, range_r) = self.query_ranges(&q[done.clone()]);
-
             // Draw query
             if pq.0 >= done.start + 1 {
                 draw_label(pq.left(max(2, done.start + 1)), "Q", canvas);
             }
-            draw_string(pq.left(done.start), &q, |_| DEFAULT, canvas);
+            // Only the part of the query that fits left of the forward array.
+            let shift = done.start.min(pq.0);
+            draw_string(
+                pq.left(shift),
+                &q[done.start - shift..],
+                |_| DEFAULT,
+                canvas,
+            );
             draw_string(pq_r.left(done.end), &q, |_| DEFAULT, canvas);
 
             // Draw current range
@@ -540,13 +1321,20 @@ impl Viz for BiBWT {
                 draw_label(cst_r.down(range_r.start), "sr/tr", canvas);
             }
 
-            // Draw cyan shading for matched chars.
-            draw_string(pq, &q[done.clone()], |_| CYAN, canvas);
-            draw_string(pq_r.left(done.len()), &q[done.clone()], |_| CYAN, canvas);
+            // Draw cyan shading for matched chars, and mismatches in orange.
+            let colour = |k: usize| {
+                if text[k] == q[done.start + k] {
+                    CYAN
+                } else {
+                    MISMATCH
+                }
+            };
+            draw_string(pq, text, colour, canvas);
+            draw_string(pq_r.left(done.len()), text, colour, canvas);
             for j in range.clone() {
                 draw_string(
                     psa.down(j),
-                    &self.s2[self.sa[j]..self.sa[j] + step],
+                    &self.s2[self.sa[j]..self.sa[j] + done.len()],
                     |_| CYAN,
                     canvas,
                 );
@@ -554,7 +1342,7 @@ impl Viz for BiBWT {
             for j in range_r.clone() {
                 draw_string(
                     psa_r.left(done.len()).down(j),
-                    &self.s2[n + self.sa_r[j] - step..n + self.sa_r[j]],
+                    &self.s2[n + self.sa_r[j] - done.len()..n + self.sa_r[j]],
                     |_| CYAN,
                     canvas,
                 );
@@ -579,30 +1367,41 @@ impl Viz for BiBWT {
             );
             draw_highlight_box(pqs, 1, 2, BLACK, canvas);
             draw_highlight_box(pqs_r, 1, 2, BLACK, canvas);
+        };
+
+        if let Query(step, qs) = state {
+            // Current state
+            let q = &self.q;
+
+            let done = self.matched(step);
+            let (range, range_r) = self.query_ranges(&q[done.clone()]);
+
+            draw_match(&done, &q[done.clone()], &range, &range_r, canvas);
 
             if qs == PreviousDone {
-                if step == 1 {
-                    draw_text(
-                        plabel,
-                        "Start with the range of the first character",
-                        canvas,
-                    );
-                } else {
-                    draw_text(plabel, "Matching done", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
             // Position of the next char to be matched.
-            let extend_left = step < mid;
-            let extend_idx = if extend_left { mid - step - 1 } else { step };
+            let Some(extend_left) = self.extends_left(step) else {
+                return true;
+            };
+            let extend_idx = if extend_left {
+                done.start - 1
+            } else {
+                done.end
+            };
             let next = q[extend_idx];
             let Some(ci) = self.alph.iter().position(|&cc| cc == next) else {
                 return true;
             };
-            let pnext = pq.left(done.start).right(extend_idx);
+            let (pnext, cnext) = if extend_left {
+                (pq.left(1), psa.left(1))
+            } else {
+                (pq.right(done.len()), psa.right(done.len()))
+            };
             let pnext_r = pq_r.left(done.end).right(extend_idx);
-            let cnext = psa.left(done.start).right(extend_idx);
             let cnext_r = psa_r.left(done.end).right(extend_idx);
 
             // Next First range
@@ -622,11 +1421,7 @@ impl Viz for BiBWT {
             draw_char_box(pnext_r, next, NEXT_CHAR, canvas);
 
             if qs == HighlightChar {
-                if extend_left {
-                    draw_text(plabel, "Extend query on the left", canvas);
-                } else {
-                    draw_text(plabel, "Extend query on the right", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -652,7 +1447,7 @@ impl Viz for BiBWT {
                 }
             }
             if qs == HighlightMatches {
-                draw_text(plabel, "Matches for next char", canvas);
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -671,19 +1466,7 @@ impl Viz for BiBWT {
                 }
             }
             if qs == EquivalenceFirst {
-                if extend_left {
-                    draw_text(
-                        plabel,
-                        "Fwd: matches in L correspond to matches in F",
-                        canvas,
-                    );
-                } else {
-                    draw_text(
-                        plabel,
-                        "Rev: matches in Lr correspond to matches in Fr",
-                        canvas,
-                    );
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -696,11 +1479,7 @@ impl Viz for BiBWT {
                 draw_highlight(pocc_r.right(ci).down(range_r.end), BLUE, canvas);
             }
             if qs == CountFirst {
-                if extend_left {
-                    draw_text(plabel, "Fwd: positions of matches", canvas);
-                } else {
-                    draw_text(plabel, "Rev: positions of matches", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -713,11 +1492,7 @@ impl Viz for BiBWT {
                 draw_label(rsigma_r.right(ci).down(2), "+", canvas);
             }
             if qs == SmallerCountFirst {
-                if extend_left {
-                    draw_text(plabel, "Fwd: number of smaller chars", canvas);
-                } else {
-                    draw_text(plabel, "Rev: number of smaller chars", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -734,11 +1509,7 @@ impl Viz for BiBWT {
                 draw_highlight_box(pfirst_r.down(ss), 1, tt - ss, BLUE, canvas);
             }
             if qs == ExtendFirst {
-                if extend_left {
-                    draw_text(plabel, "Fwd: #smaller + match-positions", canvas);
-                } else {
-                    draw_text(plabel, "Rev: #smaller + match-positions", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -758,11 +1529,7 @@ impl Viz for BiBWT {
                 draw_highlight_box(cnext.down(range.start), 1, cnt, RED, canvas);
             }
             if qs == SmallerWindowSecond {
-                if extend_left {
-                    draw_text(plabel, "Rev: range shrinks; skip chars < c", canvas);
-                } else {
-                    draw_text(plabel, "Fwd: range shrinks; skip chars < c", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -773,11 +1540,7 @@ impl Viz for BiBWT {
                 draw_highlight_box(plast_r.down(range_r.start), 1, range_r.len(), RED, canvas);
             }
             if qs == EquivalenceSecond {
-                if extend_left {
-                    draw_text(plabel, "Rev: equal to #{<c} in forward range", canvas);
-                } else {
-                    draw_text(plabel, "Fwd: equal to #{<c} in reverse range", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -792,11 +1555,7 @@ impl Viz for BiBWT {
                 draw_highlight_box(pocc_r.down(range_r.end), ci, 1, RED, canvas);
             }
             if qs == CountSecond {
-                if extend_left {
-                    draw_text(plabel, "Rev: Char counts before/after fwd range", canvas);
-                } else {
-                    draw_text(plabel, "Fwd: Char counts before/after rev range", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -817,11 +1576,7 @@ impl Viz for BiBWT {
                 draw_highlight(pocc_r.down(n + 3).left(1), RED, canvas);
             }
             if qs == ComputeSecond {
-                if extend_left {
-                    draw_text(plabel, "Rev: Total count #{<c} in forward range", canvas);
-                } else {
-                    draw_text(plabel, "Fwd: Total count #{<c} in reverse range", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -839,11 +1594,7 @@ impl Viz for BiBWT {
                 draw_highlight(pnext.down(1), RED, canvas);
             }
             if qs == ExtendStartSecond {
-                if extend_left {
-                    draw_text(plabel, "Add #{<c} to current start", canvas);
-                } else {
-                    draw_text(plabel, "Add #{<c} to current start", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
 
@@ -874,14 +1625,84 @@ impl Viz for BiBWT {
                 draw_highlight(pnext.down(2), RED, canvas);
             }
             if qs == ExtendEndSecond {
-                if extend_left {
-                    draw_text(plabel, "Add #{≤c} to current start", canvas);
-                } else {
-                    draw_text(plabel, "Add #{≤c} to current start", canvas);
-                }
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         }
+        // Search schemes: the ranges of the current node of the search tree.
+        if let Some(v) = self.scheme_node(state) {
+            let node = &self.nodes[v];
+            let search = &self.searches[node.search];
+            let Range { start, end } = node.matched;
+            draw_match(
+                &node.matched,
+                &node.text,
+                &node.range,
+                &node.range_r,
+                canvas,
+            );
+
+            // Lines between the parts of the query, where they fit.
+            let bounds = self.part_bounds();
+            for &b in &bounds[1..bounds.len() - 1] {
+                if b + pq.0 >= start {
+                    draw_highlight_box(Pos(pq.0 + b - start, pq.1), 0, 1, BLACK, canvas);
+                }
+                draw_highlight_box(pq_r.left(end).right(b), 0, 1, BLACK, canvas);
+            }
+
+            if let Extend(_) = state {
+                let u = &self.nodes[node.parent.unwrap()];
+                let pos = if start < u.matched.start {
+                    start
+                } else {
+                    end - 1
+                };
+                draw_highlight(Pos(pq.0 + pos - start, pq.1), BLUE, canvas);
+                draw_highlight(pq_r.left(end).right(pos), BLUE, canvas);
+                draw_highlight_box(plast.down(u.range.start), 1, u.range.len(), RED, canvas);
+                draw_highlight_box(
+                    plast_r.down(u.range_r.start),
+                    1,
+                    u.range_r.len(),
+                    RED,
+                    canvas,
+                );
+            }
+
+            draw_text(
+                pq.down(3).left(pq.0),
+                &format!(
+                    "search {}/{}: order {}, L {}, U {}, mismatches {}",
+                    node.search + 1,
+                    self.searches.len(),
+                    digits(search.order.iter().map(|&part| part + 1)),
+                    digits(search.lower.iter().copied()),
+                    digits(search.upper.iter().copied()),
+                    node.errors
+                ),
+                canvas,
+            );
+            draw_text(plabel, &title, canvas);
+            return true;
+        }
+        if state == Hits {
+            let hits = self.hits();
+            for &(i, _) in &hits {
+                draw_highlight_box(ps.right(i), self.q.len(), 1, BLUE, canvas);
+            }
+            let hits = hits
+                .iter()
+                .map(|(i, errors)| format!("{i} ({errors})"))
+                .join(", ");
+            draw_text(
+                pq.down(3).left(pq.0),
+                &format!("hits (mismatches): {hits}"),
+                canvas,
+            );
+            draw_text(plabel, &title, canvas);
+            return true;
+        }
         false
     }
 }
@@ -1,7 +1,12 @@
-use crate::{alg::Viz, canvas::*};
+use crate::{
+    alg::{char_map, validate_query, validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
 use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
 
-#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy)]
+#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Serialize)]
 pub enum State {
     Init,
     Rotations,
@@ -17,8 +22,16 @@ pub enum State {
     Occ(usize),
     // Finalize occurrences
     OccDone,
+    // Inverting the BWT, one per recovered char after the $
+    Invert(usize),
     // Query, one per char +1 to wrap
     Query(usize),
+    // Look up Occ at a checkpoint and scan L, for the start (0) or end (1) of the range
+    Scan(usize, usize),
+    // Keep only the sampled suffix array entries
+    SampledSa,
+    // Locate the given row of the query range, after some LF steps
+    Locate(usize, usize),
 }
 
 fn s_stats(s: &[u8]) -> (usize, usize) {
@@ -43,23 +56,41 @@ fn to_c(condition: bool) -> Color {
 }
 
 pub struct BWT {
-    s: Vec<u8>,
-    q: Vec<u8>,
+    pub(crate) s: Vec<u8>,
+    pub(crate) q: Vec<u8>,
     n: usize,
     ql: usize,
     s2: Vec<u8>,
-    alph: Vec<u8>,
+    pub(crate) alph: Vec<u8>,
     char_count: Vec<usize>,
-    char_start: Vec<usize>,
-    sa: Vec<usize>,
-    occ: Vec<Vec<i32>>,
-    j_begin_end: Vec<(usize, usize)>,
+    pub(crate) char_start: Vec<usize>,
+    pub(crate) sa: Vec<usize>,
+    pub(crate) occ: Vec<Vec<i32>>,
+    /// The range of rows starting with the last `step` characters of the query.
+    pub(crate) j_begin_end: Vec<(usize, usize)>,
+    /// Only suffix array entries that are a multiple of this are kept for locating.
+    sample_rate: usize,
+    /// Only every this many rows of Occ are stored.
+    checkpoint: usize,
 
     pub states: Vec<State>,
 }
 
 impl BWT {
-    pub fn new(s: Vec<u8>, q: Vec<u8>) -> Self {
+    pub fn new(
+        s: Vec<u8>,
+        q: Vec<u8>,
+        sample_rate: usize,
+        checkpoint: usize,
+    ) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        validate_query(&s, &q, s.len())?;
+        if sample_rate == 0 {
+            return Err(InputError::ZeroSampleRate);
+        }
+        if checkpoint == 0 {
+            return Err(InputError::ZeroCheckpoint);
+        }
         let n = s.len();
         let alph = {
             let mut alph = s.to_vec();
@@ -128,11 +159,18 @@ impl BWT {
             states.push(Occ(i));
         }
         states.push(OccDone);
+        for k in 0..n {
+            states.push(Invert(k));
+        }
         for i in 0..=ql {
             states.push(Query(i));
+            if i < ql && checkpoint > 1 {
+                states.extend([Scan(i, 0), Scan(i, 1)]);
+            }
         }
+        states.push(SampledSa);
 
-        BWT {
+        let mut bwt = BWT {
             s,
             q,
             n,
@@ -144,7 +182,211 @@ impl BWT {
             sa,
             occ,
             j_begin_end,
+            sample_rate,
+            checkpoint,
             states,
+        };
+        let (s, t) = bwt.j_begin_end[ql];
+        for j in s..t {
+            for k in 0..bwt.locate_rows(j).len() {
+                bwt.states.push(Locate(j, k));
+            }
+        }
+        Ok(bwt)
+    }
+}
+
+impl BWT {
+    /// The character in column L of row `j`.
+    pub(crate) fn last(&self, j: usize) -> u8 {
+        self.s2[self.sa[j] + self.n - 1]
+    }
+
+    /// The LF mapping: the row of the rotation starting with the last char of row `j`.
+    fn lf(&self, j: usize) -> usize {
+        let ci = self.alph.iter().position(|&c| c == self.last(j)).unwrap();
+        self.char_start[ci] + self.occ[ci][j] as usize
+    }
+
+    /// The rows visited when inverting the BWT: row `k` starts at position `n-1-k`.
+    fn inverse_rows(&self, k: usize) -> Vec<usize> {
+        let mut rows = vec![0];
+        for _ in 0..k {
+            rows.push(self.lf(*rows.last().unwrap()));
+        }
+        rows
+    }
+
+    /// The last stored row of Occ at or before row `j`.
+    fn checkpoint_row(&self, j: usize) -> usize {
+        j - j % self.checkpoint
+    }
+
+    fn sampled(&self, j: usize) -> bool {
+        self.sa[j].is_multiple_of(self.sample_rate)
+    }
+
+    /// The rows visited by LF steps from row `j` until a sampled row.
+    fn locate_rows(&self, j: usize) -> Vec<usize> {
+        let mut rows = vec![j];
+        while !self.sampled(*rows.last().unwrap()) {
+            rows.push(self.lf(*rows.last().unwrap()));
+        }
+        rows
+    }
+
+    fn explain(&self, state: State) -> Explanation {
+        match state {
+            State::Init => Explanation::new(
+                "Input string S.",
+                "The input string S, ending in the unique smallest character $.",
+            ),
+            State::Rotations => Explanation::new(
+                "Write down rotations of S.",
+                "Row j contains S rotated to start at position j.",
+            ),
+            State::SortedRotations => Explanation::new(
+                "Sort rotations via the suffix array of S.",
+                "Since $ is unique and smallest, sorting the rotations is the same as sorting \
+                 the suffixes. Column A is the suffix array: the start of each rotation.",
+            ),
+            State::FirstLast => Explanation::new(
+                "Store the first and last column.",
+                "F is the first column and L the last. L is the Burrows-Wheeler transform of S.",
+            ),
+            State::LfMap(k) => Explanation::new(
+                "For each char, L and F are sorted the same.",
+                format!(
+                    "Occurrence {k} of the most frequent character in L and in F is the same \
+                     character of S: the rotations starting with it are sorted by what follows it."
+                ),
+            ),
+            State::Counts(_) | State::CountsDone => Explanation::new(
+                "Count number of smaller characters for each c",
+                "C[c] is the number of characters smaller than c in S, \
+                 which is the first row of F starting with c.",
+            ),
+            State::Occ(_) | State::OccDone => Explanation::new(
+                "Count number of occurrences of c in L at pos < j",
+                "Occ[c][j] is the number of times c occurs in L above row j.",
+            ),
+            State::Invert(0) => Explanation::new(
+                "Invert: start in row 0",
+                "Only L, C and Occ are needed to recover S. Row 0 starts with $, the last \
+                 character of S, and its L is the character before it.",
+            ),
+            State::Invert(k) => {
+                let n = self.n;
+                let rows = self.inverse_rows(k);
+                let (prev, j) = (rows[k - 1], rows[k]);
+                let c = self.last(prev);
+                let ci = self.alph.iter().position(|&x| x == c).unwrap();
+                let i = n - 1 - k;
+                let c = to_label(c);
+                let done = if k == n - 1 {
+                    format!(" Row {j} ends in $, so all of S is recovered.")
+                } else {
+                    String::new()
+                };
+                Explanation::new(
+                    format!("S[{i}] = L[{prev}] = {c}"),
+                    format!(
+                        "Row {prev} starts at position {}, so L[{prev}] = {c} is the character \
+                         before it. The rotation starting with this {c} is in row \
+                         C[{c}] + Occ[{c}][{prev}] = {} + {} = {j}.{done}",
+                        i + 1,
+                        self.char_start[ci],
+                        self.occ[ci][prev],
+                    ),
+                )
+            }
+            State::Query(step) => {
+                let (s, t) = self.j_begin_end[step];
+                if step == 0 {
+                    Explanation::new(
+                        "Initialize the query range as the full text",
+                        format!("The empty suffix of Q matches all rows: [s, t) = [{s}, {t})."),
+                    )
+                } else if step < self.ql {
+                    let c = to_label(self.q[self.ql - 1 - step]);
+                    Explanation::new(
+                        "Update s[i-1] = C[c] + Occ[c][s[i]]",
+                        format!(
+                            "The rows starting with the last {step} characters of Q are [{s}, {t}). \
+                             Prepend the next character {c}: the new range starts at \
+                             C[{c}] + Occ[{c}][{s}] and ends at C[{c}] + Occ[{c}][{t}]."
+                        ),
+                    )
+                } else {
+                    Explanation::new(
+                        "Query done",
+                        format!("Q occurs {} times, in rows [{s}, {t}).", t - s),
+                    )
+                }
+            }
+            State::Scan(step, bound) => {
+                let c = self.q[self.ql - 1 - step];
+                let ci = self.alph.iter().position(|&x| x == c).unwrap();
+                let (s, t) = self.j_begin_end[step];
+                let j = if bound == 0 { s } else { t };
+                let cp = self.checkpoint_row(j);
+                let scanned = (cp..j).filter(|&j2| self.last(j2) == c).count();
+                let k = self.checkpoint;
+                let c = to_label(c);
+                Explanation::new(
+                    format!("Occ[{c}][{j}] = {}", self.occ[ci][j]),
+                    format!(
+                        "Occ is only stored for rows that are a multiple of {k}. Row {j} \
+                         starts from the checkpoint in row {cp}, and scanning L in rows \
+                         [{cp}, {j}) adds the {scanned} more {c} before row {j}: {} + {scanned} = {}.",
+                        self.occ[ci][cp],
+                        self.occ[ci][j]
+                    ),
+                )
+            }
+            State::SampledSa => {
+                let r = self.sample_rate;
+                Explanation::new(
+                    format!("Sample A with rate {r}"),
+                    format!(
+                        "Only the entries of A that are a multiple of {r} are kept, using n/{r} \
+                         space. The positions of the other rows are found by LF steps, each \
+                         moving one position to the left, until a sampled row."
+                    ),
+                )
+            }
+            State::Locate(j, k) => {
+                let rows = self.locate_rows(j);
+                let row = rows[k];
+                if self.sampled(row) {
+                    let pos = self.sa[row] + k;
+                    Explanation::new(
+                        format!("Row {j}: A[{row}] + {k} = {pos}"),
+                        format!(
+                            "Row {row} is sampled with A[{row}] = {}. It was reached from row \
+                             {j} in {k} LF step{}, so Q occurs at position {} + {k} = {pos}.",
+                            self.sa[row],
+                            if k == 1 { "" } else { "s" },
+                            self.sa[row]
+                        ),
+                    )
+                } else {
+                    let c = self.last(row);
+                    let ci = self.alph.iter().position(|&x| x == c).unwrap();
+                    let c = to_label(c);
+                    Explanation::new(
+                        format!("LF({row}) = {}", rows[k + 1]),
+                        format!(
+                            "The position of row {row} is not stored. Its rotation is preceded \
+                             by L[{row}] = {c}, which starts the rotation in row \
+                             C[{c}] + Occ[{c}][{row}] = {} + {} = {}, one position to the left.",
+                            self.char_start[ci],
+                            self.occ[ci][row],
+                            rows[k + 1]
+                        ),
+                    )
+                }
+            }
         }
     }
 }
@@ -159,8 +401,122 @@ impl Viz for BWT {
         self.states.len()
     }
 
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("small", SMALL_COLOUR), ("large", LARGE_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = self.states[state];
+        let n = self.n;
+        let mut trace = Map::new();
+        trace.insert("state".into(), json!(state));
+        trace.insert("s".into(), json!(String::from_utf8_lossy(&self.s)));
+        if state >= State::SortedRotations {
+            trace.insert("sa".into(), json!(self.sa));
+        }
+        if state >= State::FirstLast {
+            let bwt = self.sa.iter().map(|&i| self.s2[i + n - 1]).collect_vec();
+            trace.insert("bwt".into(), json!(String::from_utf8_lossy(&bwt)));
+        }
+        // C and Occ are filled in one character at a time.
+        if state >= State::Counts(0) {
+            let k = match state {
+                State::Counts(k) => k + 1,
+                _ => self.alph.len(),
+            };
+            trace.insert(
+                "c".into(),
+                json!(char_map(&self.alph[..k], &self.char_start)),
+            );
+        }
+        if state >= State::Occ(0) {
+            let k = match state {
+                State::Occ(k) => k + 1,
+                _ => self.alph.len(),
+            };
+            let occ = self
+                .occ
+                .iter()
+                .map(|occ| occ.iter().step_by(self.checkpoint).collect_vec());
+            trace.insert("occ".into(), json!(char_map(&self.alph[..k], occ)));
+        }
+
+        let highlight = match state {
+            State::LfMap(k) => {
+                let ci = self.char_count.iter().position_max().unwrap();
+                let row = self.char_start[ci] + k;
+                let shift_row = self.sa.iter().position(|&x| x == self.sa[row] + 1).unwrap();
+                json!({ "char": to_label(self.alph[ci]), "row": row, "shift_row": shift_row })
+            }
+            State::Counts(k) | State::Occ(k) if k < self.alph.len() => {
+                json!({ "char": to_label(self.alph[k]) })
+            }
+            State::Query(step) if step < self.ql => {
+                json!({ "char": to_label(self.q[self.ql - 1 - step]) })
+            }
+            _ => Value::Null,
+        };
+        if let State::Invert(k) = state {
+            let rows = self.inverse_rows(k);
+            let text = rows
+                .iter()
+                .rev()
+                .map(|&j| self.s2[self.sa[j]])
+                .collect_vec();
+            trace.insert("rows".into(), json!(rows));
+            trace.insert("text".into(), json!(String::from_utf8_lossy(&text)));
+        }
+        if let State::Query(step) = state {
+            let (s, t) = self.j_begin_end[step];
+            trace.insert("q".into(), json!(String::from_utf8_lossy(&self.q)));
+            trace.insert("step".into(), json!(step));
+            trace.insert("ranges".into(), json!(self.j_begin_end[..=step]));
+            trace.insert("range".into(), json!([s, t]));
+        }
+        if let State::Scan(step, bound) = state {
+            let c = self.q[self.ql - 1 - step];
+            let (s, t) = self.j_begin_end[step];
+            let j = if bound == 0 { s } else { t };
+            let cp = self.checkpoint_row(j);
+            trace.insert(
+                "scan".into(),
+                json!({
+                    "char": to_label(c),
+                    "j": j,
+                    "checkpoint": cp,
+                    "rows": (cp..j).filter(|&j2| self.last(j2) == c).collect_vec(),
+                }),
+            );
+        }
+        if state >= State::SampledSa {
+            let sampled = (0..n)
+                .map(|j| self.sampled(j).then_some(self.sa[j]))
+                .collect_vec();
+            trace.insert("sampled_sa".into(), json!(sampled));
+        }
+        if let State::Locate(j, k) = state {
+            let rows = self.locate_rows(j);
+            let row = rows[k];
+            trace.insert(
+                "locate".into(),
+                json!({
+                    "row": j,
+                    "rows": rows[..=k],
+                    "position": self.sampled(row).then_some(self.sa[row] + k),
+                }),
+            );
+        }
+        trace.insert("highlight".into(), highlight);
+        Value::Object(trace)
+    }
+
     fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
         let state = self.states[state];
+        let title = self.explain(state).title;
         draw_background(canvas);
 
         let s = &self.s;
@@ -210,7 +566,7 @@ impl Viz for BWT {
         draw_string_with_labels(ps, &s, |i| to_c(s[i] == '$' as u8), canvas);
 
         if state == State::Init {
-            draw_text(plabel, "Input string S.", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -229,7 +585,7 @@ impl Viz for BWT {
                     canvas,
                 );
             }
-            draw_text(plabel, "Write down rotations of S.", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -246,7 +602,7 @@ impl Viz for BWT {
                     canvas,
                 );
             }
-            draw_text(plabel, "Sort rotations via the suffix array of S.", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -255,7 +611,10 @@ impl Viz for BWT {
         for j in 0..n {
             let i = self.sa[j];
             draw_label(cj.down(j), &j.to_string(), canvas);
-            draw_label(ca.down(j), &i.to_string(), canvas);
+            // After sampling, only the sampled entries of A are kept.
+            if state < State::SampledSa || self.sampled(j) {
+                draw_label(ca.down(j), &i.to_string(), canvas);
+            }
             draw_string(
                 psa.down(j),
                 &self.s2[i..i + n],
@@ -265,7 +624,7 @@ impl Viz for BWT {
         }
 
         if state == State::FirstLast {
-            draw_text(plabel, "Store the first and last column.", canvas);
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -298,11 +657,7 @@ impl Viz for BWT {
                     draw_highlight(ps.right(idx + 1), BLUE, canvas);
                 }
             }
-            draw_text(
-                plabel,
-                "For each char, L and F are sorted the same.",
-                canvas,
-            );
+            draw_text(plabel, &title, canvas);
             return true;
         }
 
@@ -335,15 +690,25 @@ impl Viz for BWT {
             }
 
             if iscount {
-                draw_text(
-                    plabel,
-                    "Count number of smaller characters for each c",
-                    canvas,
-                );
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         }
 
+        // Occ[ci][j] from the last checkpoint, and the scanned rows of L after it.
+        let occ_lookup = |ci: usize, j: usize, canvas: &mut CanvasBox| {
+            let cp = self.checkpoint_row(j);
+            draw_highlight(pocc.right(ci).down(cp), BLUE, canvas);
+            if cp < j {
+                draw_highlight_box(plast.down(cp), 1, j - cp, BLUE, canvas);
+                for j2 in cp..j {
+                    if self.last(j2) == self.alph[ci] {
+                        draw_highlight(plast.down(j2), RED, canvas);
+                    }
+                }
+            }
+        };
+
         // 7. Occurrences
         {
             // Draw the first k+1 chars
@@ -358,32 +723,64 @@ impl Viz for BWT {
                     draw_highlight(rsigma.right(k), BLUE, canvas);
                 }
                 for j in 0..=n {
-                    draw_label(pocc.right(i).down(j), &self.occ[i][j].to_string(), canvas);
+                    let stored = j % self.checkpoint == 0;
+                    if stored {
+                        draw_label(pocc.right(i).down(j), &self.occ[i][j].to_string(), canvas);
+                    }
                     if j < n && self.s2[self.sa[j] + n - 1] == c {
                         if k < self.alph.len() && i == k {
                             draw_highlight(plast.down(j), BLUE, canvas);
-                            draw_highlight(pocc.right(k).down(j + 1), BLUE, canvas);
+                            if (j + 1) % self.checkpoint == 0 {
+                                draw_highlight(pocc.right(k).down(j + 1), BLUE, canvas);
+                            }
                         }
                     }
                 }
             }
 
             if isocc {
-                draw_text(
-                    plabel,
-                    "Count number of occurrences of c in L at pos < j",
-                    canvas,
-                );
+                draw_text(plabel, &title, canvas);
                 return true;
             }
         };
 
+        // 8. Inverting the BWT
+        if let State::Invert(k) = state {
+            let rows = self.inverse_rows(k);
+            let j = rows[k];
+            draw_label(pq.left(1), "S", canvas);
+            draw_label(pqs.left(1), "j", canvas);
+            for (k2, &j2) in rows.iter().enumerate() {
+                let i = n - 1 - k2;
+                draw_char_box(pq.right(i), self.s2[self.sa[j2]], to_c(k2 == k), canvas);
+                draw_label(pqs.right(i), &j2.to_string(), canvas);
+            }
+            draw_highlight_box(psa.down(j), n, 1, BLACK, canvas);
+            draw_highlight(psa.down(j), BLUE, canvas);
+            draw_highlight(ca.down(j), BLUE, canvas);
+            draw_highlight(ps.right(n - 1 - k), BLUE, canvas);
+            if k > 0 {
+                let prev = rows[k - 1];
+                let ci = self
+                    .alph
+                    .iter()
+                    .position(|&c| c == self.last(prev))
+                    .unwrap();
+                draw_highlight(plast.down(prev), BLUE, canvas);
+                draw_highlight(rsigma.right(ci), BLUE, canvas);
+                draw_highlight(pcnt.right(ci), BLUE, canvas);
+                occ_lookup(ci, prev, canvas);
+            }
+            draw_text(pbotlabel, &title, canvas);
+            return true;
+        }
+
         // Draw query
         {
             let q = &self.q;
             let step = match state {
-                State::Query(step) => step,
-                _ => unreachable!(),
+                State::Query(step) | State::Scan(step, _) => step,
+                _ => ql,
             };
 
             let q_done = &q[q.len() - step..];
@@ -449,22 +846,59 @@ impl Viz for BWT {
                     draw_highlight_box(plast.down(j_begin), 1, j_end - j_begin, BLUE, canvas);
                 }
 
-                draw_highlight(pocc.right(ci).down(j_begin), BLUE, canvas);
-                draw_highlight(pocc.right(ci).down(j_end), BLUE, canvas);
+                match state {
+                    State::Scan(_, 0) => occ_lookup(ci, j_begin, canvas),
+                    State::Scan(_, _) => occ_lookup(ci, j_end, canvas),
+                    _ if self.checkpoint == 1 => {
+                        occ_lookup(ci, j_begin, canvas);
+                        occ_lookup(ci, j_end, canvas);
+                    }
+                    _ => {}
+                }
+            }
+
+            // 9. Locating the occurrences
+            if state == State::SampledSa {
+                for j in 0..n {
+                    if self.sampled(j) {
+                        draw_highlight(ca.down(j), BLUE, canvas);
+                    }
+                }
+                draw_text(pbotlabel, &title, canvas);
+                return true;
+            }
+            if let State::Locate(j0, k) = state {
+                let rows = self.locate_rows(j0);
+                let row = rows[k];
+                for &j in &rows[..k] {
+                    draw_highlight(plast.down(j), BLACK, canvas);
+                }
+                draw_highlight_box(psa.down(row), n, 1, BLUE, canvas);
+                if self.sampled(row) {
+                    let pos = self.sa[row] + k;
+                    draw_highlight(ca.down(row), RED, canvas);
+                    draw_highlight_box(ps.right(pos), ql.max(1), 1, RED, canvas);
+                } else {
+                    let ci = self.alph.iter().position(|&c| c == self.last(row)).unwrap();
+                    draw_highlight(plast.down(row), BLUE, canvas);
+                    draw_highlight(rsigma.right(ci), BLUE, canvas);
+                    draw_highlight(pcnt.right(ci), BLUE, canvas);
+                    occ_lookup(ci, row, canvas);
+                }
+                let path = rows[..=k].iter().map(|j| j.to_string()).join(" → ");
+                draw_text(pqt.down(1).left(3), &format!("rows: {path}"), canvas);
+                draw_text(pbotlabel, &title, canvas);
+                return true;
             }
 
             // NOTE: We save each query step twice since this is a tricky part and
             // queries are typically short.
             if step == 0 {
-                draw_text(
-                    pbotlabel,
-                    "Initialize the query range as the full text",
-                    canvas,
-                );
+                draw_text(pbotlabel, &title, canvas);
                 return true;
             }
             if step < ql {
-                draw_text(pbotlabel, "Update s[i-1] = C[c] + Occ[c][s[i]]", canvas);
+                draw_text(pbotlabel, &title, canvas);
                 return true;
             }
             return true;
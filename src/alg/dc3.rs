@@ -0,0 +1,572 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    alg::{validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+const SAMPLE_COLOUR: Color = (150, 200, 255);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize)]
+pub enum State {
+    // Split positions by their value mod 3.
+    Classes,
+    // The triple starting at each sample position.
+    Triples,
+    // Stable sort of the triples on the given character.
+    SortTriples(usize),
+    // Name the sorted triples.
+    Names,
+    // Sort the sample suffixes via the reduced string.
+    Recurse,
+    // Sort the non-sample suffixes.
+    NonSample,
+    // Comparison `t` of the merge.
+    Merge(usize),
+    // Append the suffixes left after the merge.
+    Rest,
+}
+
+/// One comparison of the merge.
+#[derive(Clone, Copy, Serialize)]
+struct Comparison {
+    /// Index into the sorted non-sample suffixes.
+    a: usize,
+    /// Index into the sorted sample suffixes.
+    b: usize,
+    /// Whether the non-sample suffix is smaller.
+    less: bool,
+}
+
+/// The string at one level of the recursion, and how its suffixes are sorted.
+struct Level {
+    s: Vec<usize>,
+    /// Positions `1 mod 3` followed by positions `2 mod 3`.
+    sample: Vec<usize>,
+    /// `by_key[k]`: the sample sorted on characters `k..3` of its triple.
+    by_key: Vec<Vec<usize>>,
+    /// The name of each sample triple, in the order of `sample`: the reduced string.
+    names: Vec<usize>,
+    /// The suffix array of the reduced string.
+    reduced_sa: Vec<usize>,
+    /// The sample positions in suffix order.
+    sample_sorted: Vec<usize>,
+    /// The rank of each sample position among the sample suffixes.
+    rank: Vec<Option<usize>>,
+    /// The non-sample positions in suffix order.
+    nonsample: Vec<usize>,
+    comparisons: Vec<Comparison>,
+}
+
+impl Level {
+    /// Whether all triples are different, so that the reduced string needs no recursion.
+    fn distinct(&self) -> bool {
+        self.names
+            .iter()
+            .max()
+            .is_none_or(|&name| name + 1 == self.names.len())
+    }
+
+    fn rank(&self, i: usize) -> Option<usize> {
+        self.rank.get(i).copied().flatten()
+    }
+
+    fn name(&self, i: usize) -> usize {
+        self.names[self.sample.iter().position(|&x| x == i).unwrap()]
+    }
+
+    /// The rows of the sample table in the given state.
+    fn sample_rows(&self, state: State) -> &Vec<usize> {
+        match state {
+            State::Classes | State::Triples => &self.sample,
+            State::SortTriples(k) => &self.by_key[k],
+            State::Names => &self.by_key[0],
+            _ => &self.sample_sorted,
+        }
+    }
+
+    /// The merged suffix array so far.
+    fn merged(&self, state: State) -> Vec<usize> {
+        match state {
+            State::Merge(_) | State::Rest => {
+                let t = match state {
+                    State::Merge(t) => t + 1,
+                    _ => self.comparisons.len(),
+                };
+                let (mut a, mut b) = (0, 0);
+                let mut sa = vec![];
+                for c in &self.comparisons[..t] {
+                    if c.less {
+                        sa.push(self.nonsample[c.a]);
+                        a += 1;
+                    } else {
+                        sa.push(self.sample_sorted[c.b]);
+                        b += 1;
+                    }
+                }
+                if state == State::Rest {
+                    sa.extend(&self.nonsample[a..]);
+                    sa.extend(&self.sample_sorted[b..]);
+                }
+                sa
+            }
+            _ => vec![],
+        }
+    }
+}
+
+pub struct DC3 {
+    levels: Vec<Level>,
+    /// The recursion level of each state, and the state.
+    states: Vec<(usize, State)>,
+}
+
+impl DC3 {
+    pub fn new(s: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let mut dc3 = Self {
+            levels: vec![],
+            states: vec![],
+        };
+        dc3.sort(s.iter().map(|&c| c as usize).collect());
+        Ok(dc3)
+    }
+
+    /// Run DC3 on `s` at the next level, recording the states, and return its suffix array.
+    fn sort(&mut self, s: Vec<usize>) -> Vec<usize> {
+        let level = self.levels.len();
+        let n = s.len();
+        let sample: Vec<usize> = (1..n).step_by(3).chain((2..n).step_by(3)).collect();
+
+        // Radix sort the triples, starting with the last character.
+        // Past the end of the string, characters are empty and smallest.
+        let mut by_key = vec![vec![]; 3];
+        let mut order = sample.clone();
+        for k in (0..3).rev() {
+            order.sort_by_key(|&i| s.get(i + k));
+            by_key[k] = order.clone();
+        }
+        let triple = |i: usize| [s.get(i), s.get(i + 1), s.get(i + 2)];
+        let mut name = vec![0; n];
+        for j in 1..order.len() {
+            let new = triple(order[j]) != triple(order[j - 1]);
+            name[order[j]] = name[order[j - 1]] + usize::from(new);
+        }
+        let names: Vec<usize> = sample.iter().map(|&i| name[i]).collect();
+
+        self.levels.push(Level {
+            s,
+            sample,
+            by_key,
+            names,
+            reduced_sa: vec![],
+            sample_sorted: vec![],
+            rank: vec![],
+            nonsample: vec![],
+            comparisons: vec![],
+        });
+        self.states.extend(
+            [
+                State::Classes,
+                State::Triples,
+                State::SortTriples(2),
+                State::SortTriples(1),
+                State::SortTriples(0),
+                State::Names,
+            ]
+            .map(|state| (level, state)),
+        );
+
+        // Sort the reduced string, recursively when names are not unique.
+        let lvl = &self.levels[level];
+        let reduced_sa = if lvl.distinct() {
+            let mut reduced_sa = vec![0; lvl.names.len()];
+            for (k, &name) in lvl.names.iter().enumerate() {
+                reduced_sa[name] = k;
+            }
+            reduced_sa
+        } else {
+            // Shift the names to append a smallest sentinel, whose suffix sorts first.
+            let reduced = lvl.names.iter().map(|&name| name + 1).chain([0]).collect();
+            self.sort(reduced)[1..].to_vec()
+        };
+
+        let lvl = &mut self.levels[level];
+        let s = &lvl.s;
+        let sample_sorted: Vec<usize> = reduced_sa.iter().map(|&k| lvl.sample[k]).collect();
+        let mut rank = vec![None; n];
+        for (r, &i) in sample_sorted.iter().enumerate() {
+            rank[i] = Some(r);
+        }
+        let r = |i: usize| rank.get(i).copied().flatten();
+
+        let mut nonsample: Vec<usize> = (0..n).step_by(3).collect();
+        nonsample.sort_by_key(|&i| (s[i], r(i + 1)));
+
+        let mut comparisons = vec![];
+        let (mut a, mut b) = (0, 0);
+        while a < nonsample.len() && b < sample_sorted.len() {
+            let (i, j) = (nonsample[a], sample_sorted[b]);
+            let less = if j % 3 == 1 {
+                (s[i], r(i + 1)) < (s[j], r(j + 1))
+            } else {
+                (s[i], s.get(i + 1), r(i + 2)) < (s[j], s.get(j + 1), r(j + 2))
+            };
+            comparisons.push(Comparison { a, b, less });
+            if less {
+                a += 1;
+            } else {
+                b += 1;
+            }
+        }
+
+        self.states
+            .extend([State::Recurse, State::NonSample].map(|state| (level, state)));
+        self.states
+            .extend((0..comparisons.len()).map(|t| (level, State::Merge(t))));
+        self.states.push((level, State::Rest));
+        lvl.reduced_sa = reduced_sa;
+        lvl.sample_sorted = sample_sorted;
+        lvl.rank = rank;
+        lvl.nonsample = nonsample;
+        lvl.comparisons = comparisons;
+        lvl.merged(State::Rest)
+    }
+
+    /// Characters of the reduced strings are names shifted by one, after the sentinel.
+    fn label(level: usize, c: usize) -> String {
+        match (level, c) {
+            (0, c) => to_label(c as u8),
+            (_, 0) => "$".to_string(),
+            (_, c) => (c - 1).to_string(),
+        }
+    }
+
+    /// The name of the string at the given level: S, R, R', ...
+    fn name(level: usize) -> String {
+        match level {
+            0 => "S".to_string(),
+            _ => format!("R{}", "'".repeat(level - 1)),
+        }
+    }
+
+    /// The characters and sample rank compared for suffix `i` against sample suffix `j`,
+    /// as text and as the positions they are taken from.
+    fn key(&self, level: usize, i: usize, j: usize) -> (String, Vec<usize>) {
+        let lvl = &self.levels[level];
+        let s = &lvl.s;
+        let n = s.len();
+        let rank = |i: usize| lvl.rank(i).map_or("-".to_string(), |r| r.to_string());
+        let len = if j % 3 == 1 { 1 } else { 2 };
+        let mut parts: Vec<String> = (i..i + len)
+            .map(|i| s.get(i).map_or("-".to_string(), |&c| Self::label(level, c)))
+            .collect();
+        parts.push(rank(i + len));
+        let positions = (i..=i + len).filter(|&i| i < n).collect();
+        (format!("({})", parts.join(", ")), positions)
+    }
+
+    fn explain(&self, level: usize, state: State) -> Explanation {
+        let lvl = &self.levels[level];
+        let (name, reduced) = (Self::name(level), Self::name(level + 1));
+        match state {
+            State::Classes if level > 0 => Explanation::new(
+                format!("Recurse on {name}."),
+                format!(
+                    "The suffixes of {name}, with a smallest $ appended, are sorted with DC3. \
+                     Positions 1 and 2 mod 3 form the sample (blue)."
+                ),
+            ),
+            State::Classes => Explanation::new(
+                "Split positions mod 3.",
+                "Positions 1 and 2 mod 3 form the sample (blue). \
+                 The sample suffixes are sorted first, and then used to sort the others.",
+            ),
+            State::Triples => Explanation::new(
+                "Triples of the sample positions.",
+                "Each sample position gets the triple of characters starting there. \
+                 Past the end of the string characters are empty, and smallest.",
+            ),
+            State::SortTriples(k) => Explanation::new(
+                format!("Radix sort on character {}", k + 1),
+                format!(
+                    "Stable sort the triples on their character {}. \
+                     After sorting on all three characters from last to first, \
+                     the triples are in order.",
+                    k + 1
+                ),
+            ),
+            State::Names => Explanation::new(
+                "Name the triples.",
+                format!(
+                    "Each triple gets its rank as name. The names of the positions 1 mod 3 \
+                     followed by those of the positions 2 mod 3 form the reduced string \
+                     {reduced}."
+                ),
+            ),
+            State::Recurse if lvl.distinct() => Explanation::new(
+                "Sort the sample suffixes.",
+                format!(
+                    "All names are distinct, so the suffix array SA' of {reduced} follows \
+                     directly. Its order is the order of the sample suffixes, which gives their \
+                     ranks."
+                ),
+            ),
+            State::Recurse => Explanation::new(
+                "The sample suffixes are sorted recursively.",
+                format!(
+                    "Some names are equal, so SA' was computed by running DC3 on {reduced}, at \
+                     recursion level {}. Its order is the order of the sample suffixes, which \
+                     gives their ranks.",
+                    level + 1
+                ),
+            ),
+            State::NonSample => Explanation::new(
+                "Sort the non-sample suffixes.",
+                format!(
+                    "A suffix i = 0 mod 3 is its first character followed by the sample suffix \
+                     i+1, so radix sort the pairs ({name}[i], rank[i+1])."
+                ),
+            ),
+            State::Merge(t) => {
+                let c = lvl.comparisons[t];
+                let (i, j) = (lvl.nonsample[c.a], lvl.sample_sorted[c.b]);
+                let (ki, _) = self.key(level, i, j);
+                let (kj, _) = self.key(level, j, j);
+                let (small, large) = if c.less { (i, j) } else { (j, i) };
+                Explanation::new(
+                    format!("Compare suffixes {i} and {j}"),
+                    format!(
+                        "Suffix {j} is {} mod 3, so compare {} and their rank after that, \
+                         which are both sample suffixes: {ki} {} {kj}. \
+                         Suffix {small} is smaller than suffix {large} and goes first.",
+                        j % 3,
+                        if j % 3 == 1 {
+                            "the first character"
+                        } else {
+                            "the first two characters"
+                        },
+                        if c.less { "<" } else { ">" },
+                    ),
+                )
+            }
+            State::Rest if level > 0 => Explanation::new(
+                format!("The suffixes of {name} are sorted."),
+                format!(
+                    "One of the lists is empty, so the remaining suffixes of the other list are \
+                     appended. Without the suffix $, this is SA' at recursion level {}.",
+                    level - 1
+                ),
+            ),
+            State::Rest => Explanation::new(
+                "All suffixes are sorted.",
+                "One of the lists is empty, so the remaining suffixes of the other \
+                 list are appended.",
+            ),
+        }
+    }
+}
+
+impl Viz for DC3 {
+    fn canvas_size(&self) -> (usize, usize) {
+        let n = self.levels[0].s.len();
+        canvas_size(n + 14, n + 7)
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("sample", SAMPLE_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        let (level, state) = self.states[state];
+        self.explain(level, state)
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let (level, state) = self.states[state];
+        let lvl = &self.levels[level];
+        let names = (state >= State::Names).then_some(&lvl.names);
+        let recursed = state >= State::Recurse;
+        let s = if level == 0 {
+            let s: Vec<u8> = lvl.s.iter().map(|&c| c as u8).collect();
+            json!(String::from_utf8_lossy(&s))
+        } else {
+            json!(lvl.s)
+        };
+        json!({
+            "level": level,
+            "state": state,
+            "s": s,
+            "sample": lvl.sample,
+            "sample_rows": (state >= State::Triples).then(|| lvl.sample_rows(state)),
+            "reduced": names,
+            "reduced_sa": recursed.then_some(&lvl.reduced_sa),
+            "rank": recursed.then_some(&lvl.rank),
+            "nonsample": (state >= State::NonSample).then_some(&lvl.nonsample),
+            "sa": lvl.merged(state),
+            "compare": match state {
+                State::Merge(t) => json!(lvl.comparisons[t]),
+                _ => Value::Null,
+            },
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let (level, state) = self.states[state];
+        let title = self.explain(level, state).title;
+        let lvl = &self.levels[level];
+        let s = &lvl.s;
+        let n = s.len();
+        let label = |c: usize| Self::label(level, c);
+        draw_background(canvas);
+
+        let class_colour = |i: usize| {
+            if i.is_multiple_of(3) {
+                WHITE
+            } else {
+                SAMPLE_COLOUR
+            }
+        };
+
+        // Positioning
+
+        // Top left of S.
+        let ps = Pos(3, 1);
+        let pmod = ps.down(1);
+        // Sample table: position, triple and name or rank.
+        let pa = Pos(1, 5);
+        let pname = pa.right(4);
+        // Non-sample table: position, character and rank of the next position.
+        let pb = Pos(7, 5);
+        // The merged suffix array, and the reduced string before that.
+        let pc = Pos(11, 5);
+        let psuf = pc.right(2);
+        let plabel = Pos(0, n + 5);
+        let pkey = plabel.down(1);
+
+        if level > 0 {
+            draw_text(
+                Pos(0, self.levels[0].s.len() + 6),
+                &format!("Recursion level {level}"),
+                canvas,
+            );
+        }
+
+        draw_label(ps.left(1).up(1), "i", canvas);
+        for i in 0..n {
+            draw_label(ps.right(i).up(1), &i.to_string(), canvas);
+        }
+        draw_label(ps.left(1), &Self::name(level), canvas);
+        for (i, &c) in s.iter().enumerate() {
+            draw_text_box(ps.right(i), &label(c), class_colour(i), canvas);
+        }
+        draw_label(pmod.left(1), "mod", canvas);
+        for i in 0..n {
+            draw_label(pmod.right(i), &(i % 3).to_string(), canvas);
+        }
+        draw_text(plabel, &title, canvas);
+        if state == State::Classes {
+            return true;
+        }
+
+        // The sample table.
+        draw_label(pa.up(1), "i", canvas);
+        if state >= State::Names {
+            let header = if state >= State::Recurse {
+                "rank"
+            } else {
+                "name"
+            };
+            draw_label(pname.up(1), header, canvas);
+        }
+        let rows = lvl.sample_rows(state);
+        for (r, &i) in rows.iter().enumerate() {
+            draw_label(pa.down(r), &i.to_string(), canvas);
+            for k in 0..3 {
+                let c = s.get(i + k).map_or(String::new(), |&c| label(c));
+                draw_text_box(pa.right(1 + k).down(r), &c, SAMPLE_COLOUR, canvas);
+            }
+            if state >= State::Recurse {
+                draw_label(pname.down(r), &r.to_string(), canvas);
+            } else if state >= State::Names {
+                draw_label(pname.down(r), &lvl.name(i).to_string(), canvas);
+            }
+        }
+        if let State::SortTriples(k) = state {
+            draw_highlight_box(pa.right(1 + k), 1, rows.len(), BLUE, canvas);
+        }
+
+        // The reduced string and its suffix array.
+        if state == State::Names || state == State::Recurse {
+            draw_label(pc.right(1), &Self::name(level + 1), canvas);
+            for (k, &name) in lvl.names.iter().enumerate() {
+                draw_text_box(psuf.right(k), &name.to_string(), SAMPLE_COLOUR, canvas);
+                draw_label(psuf.right(k).up(1), &lvl.sample[k].to_string(), canvas);
+            }
+            if state == State::Recurse {
+                draw_label(pc.right(1).down(1), "SA'", canvas);
+                for (k, &k2) in lvl.reduced_sa.iter().enumerate() {
+                    draw_label(psuf.right(k).down(1), &k2.to_string(), canvas);
+                }
+            }
+        }
+        if state < State::NonSample {
+            return true;
+        }
+
+        // The non-sample table.
+        draw_label(pb.up(1), "i", canvas);
+        draw_label(pb.right(1).up(1), "S", canvas);
+        draw_label(pb.right(2).up(1), "r", canvas);
+        for (r, &i) in lvl.nonsample.iter().enumerate() {
+            draw_label(pb.down(r), &i.to_string(), canvas);
+            draw_text_box(pb.right(1).down(r), &label(s[i]), WHITE, canvas);
+            let rank = lvl.rank(i + 1).map_or("-".to_string(), |r| r.to_string());
+            draw_label(pb.right(2).down(r), &rank, canvas);
+        }
+
+        // The merged suffix array.
+        let sa = lvl.merged(state);
+        if state > State::NonSample {
+            draw_label(pc.up(1), "j", canvas);
+            draw_label(pc.right(1).up(1), "SA", canvas);
+        }
+        for (j, &i) in sa.iter().enumerate() {
+            draw_label(pc.down(j), &j.to_string(), canvas);
+            draw_label(pc.right(1).down(j), &i.to_string(), canvas);
+            for (i2, &c) in s[i..].iter().enumerate() {
+                draw_text_box(
+                    psuf.down(j).right(i2),
+                    &label(c),
+                    class_colour(i + i2),
+                    canvas,
+                );
+            }
+        }
+
+        if let State::Merge(t) = state {
+            let c = lvl.comparisons[t];
+            let (i, j) = (lvl.nonsample[c.a], lvl.sample_sorted[c.b]);
+            let (ki, pi) = self.key(level, i, j);
+            let (kj, pj) = self.key(level, j, j);
+            draw_highlight_box(pb.down(c.a), 3, 1, BLUE, canvas);
+            draw_highlight_box(pa.down(c.b), 5, 1, RED, canvas);
+            for p in pi {
+                draw_highlight(ps.right(p), BLUE, canvas);
+            }
+            for p in pj {
+                draw_highlight(ps.right(p), RED, canvas);
+            }
+            let colour = if c.less { BLUE } else { RED };
+            draw_highlight(pc.right(1).down(t), colour, canvas);
+            let cmp = if c.less { "<" } else { ">" };
+            draw_text(pkey, &format!("{ki} {cmp} {kj}"), canvas);
+        }
+        true
+    }
+}
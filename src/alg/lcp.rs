@@ -0,0 +1,326 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::max;
+
+use crate::{
+    alg::{validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+const MATCH_COLOUR: Color = (170, 230, 170);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize)]
+pub enum State {
+    Init,
+    // Look up the row of suffix `i`; its first `h` characters match the row above.
+    Rank { i: usize, h: usize },
+    // Compare character `h` of suffix `i` and of the suffix in the row above.
+    Compare { i: usize, h: usize },
+    // Write `h` as the LCP of suffix `i`.
+    Set { i: usize, h: usize },
+    End,
+}
+
+/// The suffix array of `s`, by sorting its suffixes.
+pub(crate) fn suffix_array(s: &[u8]) -> Vec<usize> {
+    let mut sa: Vec<usize> = (0..s.len()).collect();
+    sa.sort_by_key(|&i| &s[i..]);
+    sa
+}
+
+/// Kasai's algorithm: `lcp[j]` is the longest common prefix of the suffixes in rows `j-1` and `j`.
+/// Suffix `i+1` shares at least `h-1` characters with its row above when suffix `i` shares `h`.
+pub(crate) fn kasai(s: &[u8], sa: &[usize]) -> Vec<usize> {
+    let n = s.len();
+    let mut rank = vec![0; n];
+    for (j, &i) in sa.iter().enumerate() {
+        rank[i] = j;
+    }
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+    for i in 0..n {
+        if rank[i] == 0 {
+            h = 0;
+        } else {
+            let i2 = sa[rank[i] - 1];
+            while s[i + h] == s[i2 + h] {
+                h += 1;
+            }
+        }
+        lcp[rank[i]] = h;
+        h = h.saturating_sub(1);
+    }
+    lcp
+}
+
+pub struct LCP {
+    s: Vec<u8>,
+    sa: Vec<usize>,
+    rank: Vec<usize>,
+    /// `lcp[j]`: the longest common prefix of the suffixes in rows `j-1` and `j`.
+    lcp: Vec<usize>,
+    states: Vec<State>,
+}
+
+impl LCP {
+    pub fn new(s: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let n = s.len();
+        let sa = suffix_array(&s);
+        let mut rank = vec![0; n];
+        for (j, &i) in sa.iter().enumerate() {
+            rank[i] = j;
+        }
+        let lcp = kasai(&s, &sa);
+
+        // Replay Kasai's algorithm: suffix `i` compares characters from `h` up to the first
+        // mismatch at `lcp[rank[i]]`.
+        let mut states = vec![State::Init];
+        let mut h = 0;
+        for i in 0..n {
+            states.push(State::Rank { i, h });
+            let l = lcp[rank[i]];
+            if rank[i] > 0 {
+                states.extend((h..=l).map(|h| State::Compare { i, h }));
+            }
+            states.push(State::Set { i, h: l });
+            h = l.saturating_sub(1);
+        }
+        states.push(State::End);
+        Ok(Self {
+            s,
+            sa,
+            rank,
+            lcp,
+            states,
+        })
+    }
+
+    /// The LCP array as shown in the given state, with `None` for values not computed yet.
+    fn partial_lcp(&self, state: State) -> Vec<Option<usize>> {
+        let done = match state {
+            State::Init => 0,
+            State::Rank { i, .. } | State::Compare { i, .. } => i,
+            State::Set { i, .. } => i + 1,
+            State::End => self.s.len(),
+        };
+        let mut lcp = vec![None; self.s.len()];
+        for i in 0..done {
+            lcp[self.rank[i]] = Some(self.lcp[self.rank[i]]);
+        }
+        lcp
+    }
+
+    fn explain(&self, state: State) -> Explanation {
+        let s = &self.s;
+        match state {
+            State::Init => Explanation::new(
+                "Sorted suffixes and their ranks.",
+                "The LCP array stores for each row the length of the longest common prefix \
+                 with the row above. Kasai's algorithm visits the suffixes in text order, \
+                 using the inverse of the suffix array to find their row.",
+            ),
+            State::Rank { i, h } => {
+                let r = self.rank[i];
+                if r == 0 {
+                    return Explanation::new(
+                        format!("Suffix {i} is in row {r}"),
+                        "This is the first row, so there is no suffix above it and h = 0.",
+                    );
+                }
+                let prev = if h == 0 {
+                    "Start comparing at the first character.".to_string()
+                } else {
+                    format!(
+                        "Suffix {} had {} characters in common with the suffix above it. \
+                         Dropping the first character, suffix {i} still has at least h = {h} \
+                         characters in common with the suffix above it, so those are skipped.",
+                        i - 1,
+                        h + 1,
+                    )
+                };
+                Explanation::new(
+                    format!("Suffix {i} is in row {r}"),
+                    format!(
+                        "rank[{i}] = {r}, so compare with suffix {} in row {}. {prev}",
+                        self.sa[r - 1],
+                        r - 1
+                    ),
+                )
+            }
+            State::Compare { i, h } => {
+                let i2 = self.sa[self.rank[i] - 1];
+                let (c, c2) = (to_label(s[i + h]), to_label(s[i2 + h]));
+                if c == c2 {
+                    Explanation::new(
+                        format!("{c} = {c2}, h = {}", h + 1),
+                        format!("Character {h} of suffixes {i} and {i2} is equal, so increase h."),
+                    )
+                } else {
+                    Explanation::new(
+                        format!("{c} ≠ {c2}"),
+                        format!("Character {h} of suffixes {i} and {i2} differs."),
+                    )
+                }
+            }
+            State::Set { i, h } => Explanation::new(
+                format!("LCP[{}] = {h}", self.rank[i]),
+                if h > 0 {
+                    format!(
+                        "The next suffix starts with at least h - 1 = {} matching characters.",
+                        h - 1
+                    )
+                } else {
+                    "The next suffix starts with h = 0.".to_string()
+                },
+            ),
+            State::End => Explanation::new(
+                "The LCP array is complete.",
+                "h decreases by at most one per suffix and never exceeds n, \
+                 so there are at most 2n comparisons in total.",
+            ),
+        }
+    }
+}
+
+impl Viz for LCP {
+    fn canvas_size(&self) -> (usize, usize) {
+        canvas_size(max(self.s.len() + 4, 12), self.s.len() + 7)
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("match", MATCH_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = self.states[state];
+        let compare = match state {
+            State::Compare { i, h } => {
+                let i2 = self.sa[self.rank[i] - 1];
+                json!({
+                    "suffix": i,
+                    "above": i2,
+                    "h": h,
+                    "equal": self.s[i + h] == self.s[i2 + h],
+                })
+            }
+            _ => Value::Null,
+        };
+        let h = match state {
+            State::Rank { h, .. } | State::Compare { h, .. } | State::Set { h, .. } => Some(h),
+            _ => None,
+        };
+        json!({
+            "state": state,
+            "s": String::from_utf8_lossy(&self.s),
+            "sa": self.sa,
+            "rank": self.rank,
+            "lcp": self.partial_lcp(state),
+            "h": h,
+            "compare": compare,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = self.states[state];
+        let title = self.explain(state).title;
+        let s = &self.s;
+        let n = s.len();
+        draw_background(canvas);
+
+        // Positioning
+
+        // Top left of S at the top.
+        let ps = Pos(3, 1);
+        // The first label of S.
+        let ri = ps.up(1);
+        // The rank of each position.
+        let prank = ps.down(1);
+        // Top left of the suffixes.
+        let psa = Pos(3, 4);
+        let cj = psa.left(3);
+        let csa = psa.left(2);
+        let clcp = psa.left(1);
+        let plabel = Pos(0, n + 5);
+
+        draw_string_with_labels(ps, s, |_| WHITE, canvas);
+        draw_label(prank.left(1), "rank", canvas);
+        for i in 0..n {
+            draw_label(prank.right(i), &self.rank[i].to_string(), canvas);
+        }
+        draw_text(plabel, &title, canvas);
+
+        // The suffix `i`, its row, and the number of characters known to match the row above.
+        let (i, h) = match state {
+            State::Rank { i, h } | State::Compare { i, h } | State::Set { i, h } => (i, h),
+            _ => (n, 0),
+        };
+        let r = self.rank.get(i).copied();
+
+        let lcp = self.partial_lcp(state);
+        draw_label(cj.up(1), "j", canvas);
+        draw_label(csa.up(1), "SA", canvas);
+        draw_label(clcp.up(1), "LCP", canvas);
+        for (j, &i2) in self.sa.iter().enumerate() {
+            draw_label(cj.down(j), &j.to_string(), canvas);
+            draw_label(csa.down(j), &i2.to_string(), canvas);
+            if let Some(l) = lcp[j] {
+                draw_label(clcp.down(j), &l.to_string(), canvas);
+            }
+            let active = r.is_some_and(|r| r > 0 && (j == r || j + 1 == r));
+            draw_string(
+                psa.down(j),
+                &s[i2..],
+                |k| {
+                    if active && k < h {
+                        MATCH_COLOUR
+                    } else {
+                        WHITE
+                    }
+                },
+                canvas,
+            );
+        }
+
+        let Some(r) = r else {
+            return true;
+        };
+        if r > 0 {
+            draw_text(plabel.down(1), &format!("h = {h}"), canvas);
+        }
+        match state {
+            State::Rank { .. } => {
+                draw_highlight(ri.right(i), RED, canvas);
+                draw_highlight(prank.right(i), RED, canvas);
+                draw_highlight(csa.down(r), RED, canvas);
+                if r > 0 {
+                    draw_highlight(csa.down(r - 1), BLUE, canvas);
+                    if h > 0 {
+                        draw_highlight_box(ps.right(i), h, 1, BLUE, canvas);
+                    }
+                }
+            }
+            State::Compare { .. } => {
+                let i2 = self.sa[r - 1];
+                let colour = if s[i + h] == s[i2 + h] { BLUE } else { RED };
+                draw_highlight(psa.down(r).right(h), colour, canvas);
+                draw_highlight(psa.down(r - 1).right(h), colour, canvas);
+                draw_highlight(ps.right(i + h), colour, canvas);
+                draw_highlight(ps.right(i2 + h), colour, canvas);
+            }
+            State::Set { .. } => {
+                draw_highlight(clcp.down(r), RED, canvas);
+            }
+            _ => {}
+        }
+        true
+    }
+}
@@ -0,0 +1,353 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::max;
+
+use crate::{
+    alg::{
+        lcp::{kasai, suffix_array},
+        ukkonen::{tree_layout, EDGE_COLOUR, LEAF_COLOUR},
+        validate_string, Explanation, InputError, Viz,
+    },
+    canvas::*,
+};
+
+#[derive(Clone, Serialize)]
+struct Node {
+    /// The length of the common prefix of the interval: the LCP value.
+    depth: usize,
+    /// The interval of rows of the suffix array below this node.
+    lb: usize,
+    rb: Option<usize>,
+    parent: Option<usize>,
+    /// For leaves, the start of their suffix.
+    suffix: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum Step {
+    // Compare the LCP of row `j` with the depth of the top of the stack.
+    Row,
+    // The node on top of the stack is deeper than the LCP: its interval ends at row `j-1`.
+    Pop { node: usize },
+    // No node on the stack has depth LCP[j]: a new one is created above the last popped node.
+    NewNode { node: usize },
+    // Push the leaf of row `j`.
+    Leaf { node: usize },
+    Done,
+}
+
+#[derive(Clone, Serialize)]
+pub struct State {
+    /// The current row; `n` for the final pops.
+    j: usize,
+    step: Step,
+    nodes: Vec<Node>,
+    stack: Vec<usize>,
+}
+
+pub struct LcpTree {
+    s: Vec<u8>,
+    sa: Vec<usize>,
+    /// `lcp[j]`: the longest common prefix of the suffixes in rows `j-1` and `j`.
+    lcp: Vec<usize>,
+    states: Vec<State>,
+    rows: usize,
+    width: usize,
+}
+
+impl LcpTree {
+    pub fn new(s: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let n = s.len();
+        let sa = suffix_array(&s);
+        let lcp = kasai(&s, &sa);
+
+        let mut nodes = vec![Node {
+            depth: 0,
+            lb: 0,
+            rb: None,
+            parent: None,
+            suffix: None,
+        }];
+        let mut stack = vec![0];
+        let mut states = vec![];
+        for j in 0..=n {
+            // After the last row, everything but the root is popped.
+            let h = if j < n { lcp[j] } else { 0 };
+            let push = |states: &mut Vec<State>, step, nodes: &Vec<Node>, stack: &Vec<usize>| {
+                states.push(State {
+                    j,
+                    step,
+                    nodes: nodes.clone(),
+                    stack: stack.clone(),
+                })
+            };
+            push(&mut states, Step::Row, &nodes, &stack);
+            while nodes[*stack.last().unwrap()].depth > h {
+                let node = stack.pop().unwrap();
+                nodes[node].rb = Some(j - 1);
+                let top = *stack.last().unwrap();
+                nodes[node].parent = Some(top);
+                push(&mut states, Step::Pop { node }, &nodes, &stack);
+                if nodes[top].depth < h {
+                    let new = nodes.len();
+                    nodes.push(Node {
+                        depth: h,
+                        lb: nodes[node].lb,
+                        rb: None,
+                        parent: None,
+                        suffix: None,
+                    });
+                    nodes[node].parent = Some(new);
+                    stack.push(new);
+                    push(&mut states, Step::NewNode { node: new }, &nodes, &stack);
+                }
+            }
+            if j < n {
+                let leaf = nodes.len();
+                nodes.push(Node {
+                    depth: n - sa[j],
+                    lb: j,
+                    rb: Some(j),
+                    parent: None,
+                    suffix: Some(sa[j]),
+                });
+                stack.push(leaf);
+                push(&mut states, Step::Leaf { node: leaf }, &nodes, &stack);
+            }
+        }
+        nodes[0].rb = Some(n - 1);
+        states.push(State {
+            j: n,
+            step: Step::Done,
+            nodes,
+            stack,
+        });
+
+        let mut alg = Self {
+            s,
+            sa,
+            lcp,
+            states,
+            rows: 0,
+            width: 0,
+        };
+        for state in &alg.states {
+            for (x, row) in alg.layout(state).into_iter().flatten() {
+                alg.width = max(alg.width, x + 1);
+                alg.rows = max(alg.rows, row + 1);
+            }
+        }
+        Ok(alg)
+    }
+
+    /// The parent of each node, where nodes on the stack hang below the node under them.
+    fn parents(&self, state: &State) -> Vec<Option<usize>> {
+        let mut parents: Vec<Option<usize>> = state.nodes.iter().map(|v| v.parent).collect();
+        for w in state.stack.windows(2) {
+            parents[w[1]] = Some(w[0]);
+        }
+        parents
+    }
+
+    fn children(&self, state: &State, parents: &[Option<usize>], u: usize) -> Vec<usize> {
+        let mut children: Vec<usize> = (0..state.nodes.len())
+            .filter(|&v| parents[v] == Some(u))
+            .collect();
+        children.sort_by_key(|&v| state.nodes[v].lb);
+        children
+    }
+
+    fn layout(&self, state: &State) -> Vec<Option<(usize, usize)>> {
+        let parents = self.parents(state);
+        tree_layout(state.nodes.len(), 0, |u| {
+            self.children(state, &parents, u)
+                .into_iter()
+                .map(|v| (v, state.nodes[v].depth - state.nodes[u].depth))
+                .collect()
+        })
+    }
+
+    fn interval(node: &Node) -> String {
+        match node.rb {
+            Some(rb) => format!("[{}, {rb}]", node.lb),
+            None => format!("[{}, ..]", node.lb),
+        }
+    }
+
+    fn explain(&self, state: &State) -> Explanation {
+        let n = self.s.len();
+        let j = state.j;
+        match state.step {
+            Step::Row if j == n => Explanation::new(
+                "Close the remaining intervals.",
+                "After the last row, all nodes except the root are popped.",
+            ),
+            Step::Row => Explanation::new(
+                format!("Row {j}: LCP[{j}] = {}", self.lcp[j]),
+                format!(
+                    "Suffix {} shares {} characters with the row above. The stack holds the \
+                     path to the previous leaf; nodes deeper than that are complete.",
+                    self.sa[j], self.lcp[j]
+                ),
+            ),
+            Step::Pop { node } => {
+                let v = &state.nodes[node];
+                let what = if v.suffix.is_some() { "Leaf" } else { "Node" };
+                Explanation::new(
+                    format!("Pop node {node} with interval {}", Self::interval(v)),
+                    format!(
+                        "{what} {node} has depth {}, deeper than the LCP, so its interval of \
+                         rows ends at row {}. The rows of an internal node are exactly the \
+                         suffixes below it, which all share its {} characters.",
+                        v.depth,
+                        v.rb.unwrap(),
+                        v.depth,
+                    ),
+                )
+            }
+            Step::NewNode { node } => {
+                let v = &state.nodes[node];
+                Explanation::new(
+                    format!("New node {node} at depth {}", v.depth),
+                    format!(
+                        "The node below has depth less than {}, so a new node is needed where \
+                         rows {} and {j} branch. Its interval starts at row {} of the popped \
+                         node, which becomes its first child.",
+                        v.depth,
+                        j - 1,
+                        v.lb
+                    ),
+                )
+            }
+            Step::Leaf { .. } => {
+                let parent = state.stack[state.stack.len() - 2];
+                Explanation::new(
+                    format!("Push the leaf of suffix {}", self.sa[j]),
+                    format!(
+                        "The top of the stack, node {parent}, has depth LCP[{j}] = {}, so the \
+                         leaf hangs below it. Its interval is the single row {j}.",
+                        state.nodes[parent].depth
+                    ),
+                )
+            }
+            Step::Done => Explanation::new(
+                "The suffix tree is complete.",
+                "Each internal node corresponds to an LCP interval of rows of the suffix array.",
+            ),
+        }
+    }
+}
+
+impl Viz for LcpTree {
+    fn canvas_size(&self) -> (usize, usize) {
+        let n = self.s.len();
+        canvas_size(max(n + 6 + self.width + 1, 24), max(n, self.rows) + 6)
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("edge", EDGE_COLOUR), ("leaf", LEAF_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(&self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = &self.states[state];
+        let parents = self.parents(state);
+        json!({
+            "j": state.j,
+            "step": state.step,
+            "s": String::from_utf8_lossy(&self.s),
+            "sa": self.sa,
+            "lcp": self.lcp,
+            "nodes": state.nodes,
+            "parents": parents,
+            "stack": state.stack,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = &self.states[state];
+        let title = self.explain(state).title;
+        let s = &self.s;
+        let n = s.len();
+        draw_background(canvas);
+
+        // Positioning
+
+        // Top left of the suffixes.
+        let psa = Pos(3, 2);
+        let cj = psa.left(3);
+        let csa = psa.left(2);
+        let clcp = psa.left(1);
+        // The root of the tree, right of the table.
+        let pt = psa.right(n + 2);
+        let plabel = Pos(0, max(n, self.rows) + 3);
+        let pstack = plabel.down(1);
+
+        // The table.
+        draw_label(cj.up(1), "j", canvas);
+        draw_label(csa.up(1), "SA", canvas);
+        draw_label(clcp.up(1), "LCP", canvas);
+        for (j, &i) in self.sa.iter().enumerate() {
+            draw_label(cj.down(j), &j.to_string(), canvas);
+            draw_label(csa.down(j), &i.to_string(), canvas);
+            draw_label(clcp.down(j), &self.lcp[j].to_string(), canvas);
+            draw_string(psa.down(j), &s[i..], |_| WHITE, canvas);
+        }
+        draw_text(plabel, &title, canvas);
+        let stack: Vec<String> = state
+            .stack
+            .iter()
+            .map(|&v| format!("{v} (depth {})", state.nodes[v].depth))
+            .collect();
+        draw_text(pstack, &format!("stack: {}", stack.join(", ")), canvas);
+
+        // The tree.
+        let parents = self.parents(state);
+        let pos = self.layout(state);
+        let at = |u: usize| {
+            let (x, row) = pos[u].unwrap();
+            pt.right(x).down(row)
+        };
+        for (v, node) in state.nodes.iter().enumerate() {
+            if let Some(u) = parents[v] {
+                let start = self.sa[node.lb] + state.nodes[u].depth;
+                let label = &s[start..self.sa[node.lb] + node.depth];
+                let p = at(v).left(label.len());
+                draw_string(p, label, |_| EDGE_COLOUR, canvas);
+                draw_edge(at(u), p, BLACK, canvas);
+            }
+            match node.suffix {
+                Some(i) => draw_node(at(v), &i.to_string(), LEAF_COLOUR, canvas),
+                None => draw_node(at(v), &v.to_string(), WHITE, canvas),
+            }
+        }
+        // Nodes on the stack have their intervals still open.
+        for &v in &state.stack {
+            draw_highlight(at(v), RED, canvas);
+        }
+
+        if state.j < n {
+            draw_highlight(clcp.down(state.j), RED, canvas);
+            draw_highlight(csa.down(state.j), RED, canvas);
+        }
+        match state.step {
+            Step::Pop { node } | Step::NewNode { node } | Step::Leaf { node } => {
+                let v = &state.nodes[node];
+                let rb = v.rb.unwrap_or(state.j.min(n - 1));
+                draw_highlight_box(psa.down(v.lb), v.depth.max(1), rb - v.lb + 1, BLUE, canvas);
+                draw_highlight_box(csa.down(v.lb), 1, rb - v.lb + 1, BLUE, canvas);
+                draw_highlight(at(node), BLUE, canvas);
+            }
+            _ => {}
+        }
+        true
+    }
+}
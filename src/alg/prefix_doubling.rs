@@ -0,0 +1,358 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    alg::{validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+const FIRST_COLOUR: Color = (150, 200, 255);
+const SECOND_COLOUR: Color = (255, 200, 130);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize)]
+pub enum Step {
+    // List the pair (rank[i], rank[i+k]) of each suffix.
+    Pairs,
+    // Stable counting sort on the second key.
+    SortSecond,
+    // Stable counting sort on the first key.
+    SortFirst,
+    // Rank the sorted pairs.
+    Ranks,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize)]
+pub enum State {
+    Init,
+    Round(usize, Step),
+    End,
+}
+
+/// One round of doubling, comparing the first `2k` characters of each suffix.
+struct Round {
+    k: usize,
+    /// The rank of each suffix by its first `k` characters.
+    rank: Vec<usize>,
+    /// The order at the start of the round.
+    order: Vec<usize>,
+    /// The order after sorting on the second key.
+    by_second: Vec<usize>,
+    /// The order after sorting on both keys.
+    sorted: Vec<usize>,
+    /// The rank of each suffix by its first `2k` characters.
+    new_rank: Vec<usize>,
+}
+
+impl Round {
+    fn second(&self, i: usize) -> Option<usize> {
+        self.rank.get(i + self.k).copied()
+    }
+
+    /// Whether suffix `i` had the same rank as another suffix, but is told apart from it now.
+    fn resolved(&self, i: usize) -> bool {
+        (0..self.rank.len()).any(|i2| {
+            i2 != i && self.rank[i2] == self.rank[i] && self.new_rank[i2] != self.new_rank[i]
+        })
+    }
+}
+
+/// Stable counting sort of `order` on `key`, with keys in `0..sigma`.
+fn counting_sort(order: &[usize], sigma: usize, key: impl Fn(usize) -> usize) -> Vec<usize> {
+    let mut start = vec![0; sigma + 1];
+    for &i in order {
+        start[key(i) + 1] += 1;
+    }
+    for c in 0..sigma {
+        start[c + 1] += start[c];
+    }
+    let mut sorted = vec![0; order.len()];
+    for &i in order {
+        sorted[start[key(i)]] = i;
+        start[key(i)] += 1;
+    }
+    sorted
+}
+
+/// Consecutive ranks for `order`, where neighbours with the same key share a rank.
+fn ranks<K: PartialEq>(order: &[usize], key: impl Fn(usize) -> K) -> Vec<usize> {
+    let mut rank = vec![0; order.len()];
+    for j in 1..order.len() {
+        let same = key(order[j]) == key(order[j - 1]);
+        rank[order[j]] = rank[order[j - 1]] + usize::from(!same);
+    }
+    rank
+}
+
+pub struct PrefixDoubling {
+    s: Vec<u8>,
+    /// The rank of each suffix by its first character.
+    rank: Vec<usize>,
+    rounds: Vec<Round>,
+    states: Vec<State>,
+}
+
+impl PrefixDoubling {
+    pub fn new(s: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let n = s.len();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by_key(|&i| s[i]);
+        let rank = ranks(&order, |i| s[i]);
+
+        let mut rounds = vec![];
+        let mut states = vec![State::Init];
+        let mut cur = rank.clone();
+        // Rows start in text order, and keep the order of the previous round after that.
+        let mut order: Vec<usize> = (0..n).collect();
+        let mut k = 1;
+        while cur.iter().max() != Some(&(n - 1)) {
+            let second = |i: usize| cur.get(i + k).map_or(0, |&r| r + 1);
+            let by_second = counting_sort(&order, n + 1, second);
+            let sorted = counting_sort(&by_second, n, |i| cur[i]);
+            let new_rank = ranks(&sorted, |i| (cur[i], second(i)));
+            states.extend(
+                [Step::Pairs, Step::SortSecond, Step::SortFirst, Step::Ranks]
+                    .map(|step| State::Round(rounds.len(), step)),
+            );
+            rounds.push(Round {
+                k,
+                rank: cur,
+                order,
+                by_second,
+                sorted: sorted.clone(),
+                new_rank: new_rank.clone(),
+            });
+            cur = new_rank;
+            order = sorted;
+            k *= 2;
+        }
+        states.push(State::End);
+        Ok(Self {
+            s,
+            rank,
+            rounds,
+            states,
+        })
+    }
+
+    /// The rows of the table in the given state.
+    fn order(&self, state: State) -> Vec<usize> {
+        match state {
+            State::Init => (0..self.s.len()).collect(),
+            State::Round(r, step) => {
+                let round = &self.rounds[r];
+                match step {
+                    Step::Pairs => round.order.clone(),
+                    Step::SortSecond => round.by_second.clone(),
+                    Step::SortFirst | Step::Ranks => round.sorted.clone(),
+                }
+            }
+            State::End => self.suffix_array(),
+        }
+    }
+
+    fn suffix_array(&self) -> Vec<usize> {
+        let rank = self
+            .rounds
+            .last()
+            .map_or(&self.rank, |round| &round.new_rank);
+        let mut sa = vec![0; rank.len()];
+        for (i, &r) in rank.iter().enumerate() {
+            sa[r] = i;
+        }
+        sa
+    }
+
+    fn explain(&self, state: State) -> Explanation {
+        match state {
+            State::Init => Explanation::new(
+                "Rank by the first character.",
+                "Each suffix gets the rank of its first character among the distinct characters. \
+                 Suffixes with the same rank are tied.",
+            ),
+            State::Round(r, step) => {
+                let k = self.rounds[r].k;
+                match step {
+                    Step::Pairs => Explanation::new(
+                        format!("Round k = {k}: rank pairs"),
+                        format!(
+                            "The first {} characters of suffix i are its first {k} characters \
+                             followed by the first {k} characters of suffix i+{k}, so they are \
+                             ordered by the pair (rank[i], rank[i+{k}]). \
+                             Past the end of the string the second rank is empty and smallest.",
+                            2 * k
+                        ),
+                    ),
+                    Step::SortSecond => Explanation::new(
+                        format!("Round k = {k}: sort by second key"),
+                        format!(
+                            "Radix sort the pairs: first a stable counting sort on rank[i+{k}]."
+                        ),
+                    ),
+                    Step::SortFirst => Explanation::new(
+                        format!("Round k = {k}: sort by first key"),
+                        "Then a stable counting sort on rank[i]. Since it is stable, suffixes with \
+                         the same first key stay ordered by their second key.",
+                    ),
+                    Step::Ranks => Explanation::new(
+                        format!("Round k = {k}: new ranks"),
+                        format!(
+                            "Suffixes get the rank of their pair, now ordering them by their \
+                             first {} characters. Ties between suffixes with different pairs are \
+                             resolved (red).",
+                            2 * k
+                        ),
+                    ),
+                }
+            }
+            State::End => Explanation::new(
+                "All suffixes are sorted.",
+                "All ranks are distinct, so the order of the rows is the suffix array.",
+            ),
+        }
+    }
+}
+
+impl Viz for PrefixDoubling {
+    fn canvas_size(&self) -> (usize, usize) {
+        canvas_size(self.s.len() + 7, self.s.len() + 4)
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("first key", FIRST_COLOUR), ("second key", SECOND_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = self.states[state];
+        let s = &self.s;
+        let round = match state {
+            State::Round(r, step) => {
+                let round = &self.rounds[r];
+                let n = s.len();
+                json!({
+                    "k": round.k,
+                    "rank": round.rank,
+                    "second": (0..n).map(|i| round.second(i)).collect::<Vec<_>>(),
+                    "new_rank": (step == Step::Ranks).then_some(&round.new_rank),
+                    "resolved": (step == Step::Ranks)
+                        .then(|| (0..n).filter(|&i| round.resolved(i)).collect::<Vec<_>>()),
+                })
+            }
+            _ => Value::Null,
+        };
+        json!({
+            "state": state,
+            "s": String::from_utf8_lossy(s),
+            "order": self.order(state),
+            "rank": (state == State::Init).then_some(&self.rank),
+            "round": round,
+            "sa": (state == State::End).then(|| self.suffix_array()),
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = self.states[state];
+        let title = self.explain(state).title;
+        let s = &self.s;
+        draw_background(canvas);
+
+        // Positioning
+
+        let plabel = Pos(0, 0);
+        // Top of the j column.
+        let pj = Pos(0, 3);
+        let pi = pj.right(1);
+        // The rank pair, and the new rank.
+        let pfirst = pj.right(2);
+        let psecond = pj.right(3);
+        let prank = pj.right(4);
+        // Top left of the suffixes.
+        let psuf = pj.right(6);
+
+        draw_text(plabel, &title, canvas);
+        draw_label(pj.up(1), "j", canvas);
+        draw_label(pi.up(1), "i", canvas);
+
+        let round = match state {
+            State::Round(r, step) => Some((&self.rounds[r], step)),
+            _ => None,
+        };
+        let rank: Option<&Vec<usize>> = match (state, round) {
+            (State::Init, _) => Some(&self.rank),
+            (_, Some((round, Step::Ranks))) => Some(&round.new_rank),
+            (State::End, _) => self
+                .rounds
+                .last()
+                .map(|round| &round.new_rank)
+                .or(Some(&self.rank)),
+            _ => None,
+        };
+        if round.is_some() {
+            draw_label(pfirst.up(1), "r1", canvas);
+            draw_label(psecond.up(1), "r2", canvas);
+        }
+        if rank.is_some() {
+            draw_label(prank.up(1), "rank", canvas);
+        }
+
+        let n = s.len();
+        let order = self.order(state);
+        // Characters covered by the first and second key, or the current rank.
+        let (k1, k2) = match (state, round) {
+            (State::Init, _) => (1, 0),
+            (_, Some((round, Step::Ranks))) => (2 * round.k, 0),
+            (_, Some((round, _))) => (round.k, round.k),
+            _ => (0, 0),
+        };
+        for (j, &i) in order.iter().enumerate() {
+            draw_label(pj.down(j), &j.to_string(), canvas);
+            draw_label(pi.down(j), &i.to_string(), canvas);
+            if let Some((round, _)) = round {
+                draw_label(pfirst.down(j), &round.rank[i].to_string(), canvas);
+                let second = round.second(i).map_or("-".to_string(), |r| r.to_string());
+                draw_label(psecond.down(j), &second, canvas);
+            }
+            if let Some(rank) = rank {
+                draw_label(prank.down(j), &rank[i].to_string(), canvas);
+            }
+            draw_string(
+                psuf.down(j),
+                &s[i..],
+                |i2| {
+                    if i2 < k1 {
+                        FIRST_COLOUR
+                    } else if i2 < k1 + k2 {
+                        SECOND_COLOUR
+                    } else {
+                        WHITE
+                    }
+                },
+                canvas,
+            );
+        }
+
+        if let Some((round, step)) = round {
+            match step {
+                Step::SortSecond => draw_highlight_box(psecond, 1, n, BLUE, canvas),
+                Step::SortFirst => draw_highlight_box(pfirst, 1, n, BLUE, canvas),
+                Step::Ranks => {
+                    for (j, &i) in order.iter().enumerate() {
+                        if round.resolved(i) {
+                            draw_highlight(prank.down(j), RED, canvas);
+                        }
+                    }
+                }
+                Step::Pairs => {}
+            }
+        }
+        true
+    }
+}
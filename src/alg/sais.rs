@@ -0,0 +1,526 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::max;
+
+use crate::{
+    alg::{
+        char_map,
+        suffix_array::{bucket_of, buckets, small_suffixes, LARGE_COLOUR, SMALL_COLOUR},
+        validate_string, Explanation, InputError, Viz,
+    },
+    canvas::*,
+};
+
+const DEFAULT: Color = (240, 240, 240);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize)]
+pub enum Step {
+    // Classify suffixes as small (S) or large (L).
+    Types,
+    // Mark the leftmost small suffixes of each run (LMS).
+    Lms,
+    // LMS suffix `i` goes at the tail of its bucket.
+    PlaceLms { i: usize, row: usize },
+    // Row `j` induces the large suffix `i` at the head of its bucket.
+    InduceL { j: usize, i: usize, row: usize },
+    // Remove the small suffixes, they are induced again.
+    ClearS,
+    // Row `j` induces the small suffix `i` at the tail of its bucket.
+    InduceS { j: usize, i: usize, row: usize },
+    // Name the sorted LMS substrings.
+    Names,
+    // The order of the LMS suffixes, via the reduced string.
+    ReducedSa,
+    Done,
+}
+
+#[derive(Clone, Serialize)]
+pub struct State {
+    level: usize,
+    // 1: sorting LMS substrings, 2: sorting suffixes from the sorted LMS suffixes.
+    pass: usize,
+    step: Step,
+    sa: Vec<Option<usize>>,
+    // Bucket heads while inducing large suffixes, tails otherwise.
+    pointers: Vec<usize>,
+}
+
+/// The string at one level of the recursion.
+struct Level {
+    s: Vec<usize>,
+    small: Vec<bool>,
+    lms: Vec<usize>,
+    buckets: Vec<usize>,
+    // The name of each LMS substring, in text order: the reduced string.
+    reduced: Vec<usize>,
+    // The suffix array of the reduced string.
+    reduced_sa: Vec<usize>,
+}
+
+impl Level {
+    fn is_lms(&self, i: usize) -> bool {
+        i > 0 && self.small[i] && !self.small[i - 1]
+    }
+
+    /// The end of the LMS substring starting at LMS position `i`.
+    fn lms_end(&self, i: usize) -> usize {
+        (i + 1..self.s.len()).find(|&j| self.is_lms(j)).unwrap_or(i)
+    }
+}
+
+pub struct SAIS {
+    text: Vec<u8>,
+    levels: Vec<Level>,
+    states: Vec<State>,
+}
+
+impl SAIS {
+    pub fn new(s: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let mut sais = SAIS {
+            text: s.clone(),
+            levels: vec![],
+            states: vec![],
+        };
+        sais.sort(s.iter().map(|&c| c as usize).collect(), 0);
+        Ok(sais)
+    }
+
+    fn push(
+        &mut self,
+        level: usize,
+        pass: usize,
+        step: Step,
+        sa: &[Option<usize>],
+        ptrs: &[usize],
+    ) {
+        self.states.push(State {
+            level,
+            pass,
+            step,
+            sa: sa.to_vec(),
+            pointers: ptrs.to_vec(),
+        });
+    }
+
+    /// Run SA-IS on `s`, recording the states, and return its suffix array.
+    fn sort(&mut self, s: Vec<usize>, level: usize) -> Vec<usize> {
+        let n = s.len();
+        let small = small_suffixes(&s);
+        let lms = (1..n).filter(|&i| small[i] && !small[i - 1]).collect();
+        let buckets = buckets(&s);
+        self.levels.push(Level {
+            s,
+            small,
+            lms,
+            buckets,
+            reduced: vec![],
+            reduced_sa: vec![],
+        });
+        self.push(level, 1, Step::Types, &vec![None; n], &[]);
+        self.push(level, 1, Step::Lms, &vec![None; n], &[]);
+
+        // Sort the LMS substrings by inducing from the LMS suffixes in text order.
+        let lms = self.levels[level].lms.clone();
+        let sa = self.induce(level, 1, &lms);
+
+        // Name the LMS substrings in sorted order; equal substrings get the same name.
+        let lvl = &self.levels[level];
+        let sorted_lms = sa
+            .iter()
+            .map(|i| i.unwrap())
+            .filter(|&i| lvl.is_lms(i))
+            .collect::<Vec<_>>();
+        let substring = |i: usize| {
+            let end = lvl.lms_end(i);
+            (&lvl.s[i..=end], &lvl.small[i..=end])
+        };
+        let mut name = vec![0; n];
+        for w in sorted_lms.windows(2) {
+            name[w[1]] = name[w[0]] + (substring(w[0]) != substring(w[1])) as usize;
+        }
+        let reduced = lms.iter().map(|&i| name[i]).collect::<Vec<_>>();
+        // The last suffix is always an LMS suffix.
+        let unique = name[*sorted_lms.last().unwrap()] + 1 == lms.len();
+        self.levels[level].reduced = reduced.clone();
+        self.push(level, 1, Step::Names, &sa, &[]);
+
+        // Sort the reduced string, recursively when names are not unique.
+        let reduced_sa = if unique {
+            let mut reduced_sa = vec![0; reduced.len()];
+            for (k, &c) in reduced.iter().enumerate() {
+                reduced_sa[c] = k;
+            }
+            reduced_sa
+        } else {
+            self.sort(reduced, level + 1)
+        };
+        self.levels[level].reduced_sa = reduced_sa.clone();
+        self.push(level, 2, Step::ReducedSa, &vec![None; n], &[]);
+
+        // Induce all suffixes from the sorted LMS suffixes.
+        let sorted_lms = reduced_sa.iter().map(|&k| lms[k]).collect::<Vec<_>>();
+        let sa = self.induce(level, 2, &sorted_lms);
+        self.push(level, 2, Step::Done, &sa, &[]);
+        sa.into_iter().map(|i| i.unwrap()).collect()
+    }
+
+    /// Place the LMS suffixes in the given order at the tails of their buckets,
+    /// and induce the large and then small suffixes from them.
+    fn induce(&mut self, level: usize, pass: usize, lms: &[usize]) -> Vec<Option<usize>> {
+        let lvl = &self.levels[level];
+        let (s, small, buckets) = (lvl.s.clone(), lvl.small.clone(), lvl.buckets.clone());
+        let n = s.len();
+        let sigma = buckets.len() - 1;
+        let mut sa = vec![None; n];
+
+        let mut tails = buckets[1..].to_vec();
+        for &i in lms.iter().rev() {
+            tails[s[i]] -= 1;
+            sa[tails[s[i]]] = Some(i);
+            self.push(
+                level,
+                pass,
+                Step::PlaceLms {
+                    i,
+                    row: tails[s[i]],
+                },
+                &sa,
+                &tails,
+            );
+        }
+
+        let mut heads = buckets[..sigma].to_vec();
+        for j in 0..n {
+            let Some(i) = sa[j] else { continue };
+            if i == 0 || small[i - 1] {
+                continue;
+            }
+            let row = heads[s[i - 1]];
+            sa[row] = Some(i - 1);
+            heads[s[i - 1]] += 1;
+            self.push(level, pass, Step::InduceL { j, i: i - 1, row }, &sa, &heads);
+        }
+
+        // The last suffix is the only one in its bucket and stays.
+        for entry in &mut sa {
+            if matches!(*entry, Some(i) if small[i] && i != n - 1) {
+                *entry = None;
+            }
+        }
+        let mut tails = buckets[1..].to_vec();
+        self.push(level, pass, Step::ClearS, &sa, &tails);
+        for j in (0..n).rev() {
+            let Some(i) = sa[j] else { continue };
+            if i == 0 || !small[i - 1] {
+                continue;
+            }
+            tails[s[i - 1]] -= 1;
+            let row = tails[s[i - 1]];
+            sa[row] = Some(i - 1);
+            self.push(level, pass, Step::InduceS { j, i: i - 1, row }, &sa, &tails);
+        }
+        sa
+    }
+
+    fn label(&self, level: usize, c: usize) -> String {
+        if level == 0 {
+            to_label(c as u8)
+        } else {
+            c.to_string()
+        }
+    }
+
+    fn explain(&self, state: &State) -> Explanation {
+        let lvl = &self.levels[state.level];
+        let string = if state.level == 0 {
+            "S".to_string()
+        } else {
+            format!("the reduced string of level {}", state.level - 1)
+        };
+        match state.step {
+            Step::Types => Explanation::new(
+                "Classify suffixes as S or L",
+                format!(
+                    "Scanning {string} from right to left, a suffix is small (S, green) when it \
+                     is smaller than the suffix after it, and large (L, red) otherwise. \
+                     The last suffix is small."
+                ),
+            ),
+            Step::Lms => Explanation::new(
+                "Mark LMS positions",
+                format!(
+                    "A leftmost small (LMS) suffix is a small suffix directly after a large one. \
+                     There are {} of them.",
+                    lvl.lms.len()
+                ),
+            ),
+            Step::PlaceLms { i, row } => Explanation::new(
+                if state.pass == 1 {
+                    "Put LMS suffixes at bucket tails"
+                } else {
+                    "Put sorted LMS suffixes at bucket tails"
+                },
+                format!(
+                    "Suffix {i} starts with {}, so it goes at the current tail of that bucket: row {row}.",
+                    self.label(state.level, lvl.s[i])
+                ),
+            ),
+            Step::InduceL { j, i, row } => Explanation::new(
+                "Induce L-type suffixes from left to right",
+                format!(
+                    "SA[{j}] = {} and suffix {i} before it is large, so it goes at the head of \
+                     the bucket of {}: row {row}.",
+                    i + 1,
+                    self.label(state.level, lvl.s[i])
+                ),
+            ),
+            Step::ClearS => Explanation::new(
+                "Remove the S-type suffixes",
+                "The small suffixes are induced again from the sorted large suffixes.",
+            ),
+            Step::InduceS { j, i, row } => Explanation::new(
+                "Induce S-type suffixes from right to left",
+                format!(
+                    "SA[{j}] = {} and suffix {i} before it is small, so it goes at the tail of \
+                     the bucket of {}: row {row}.",
+                    i + 1,
+                    self.label(state.level, lvl.s[i])
+                ),
+            ),
+            Step::Names => Explanation::new(
+                "Name the sorted LMS substrings",
+                "The LMS substrings are now sorted. Each gets the rank of its distinct value as \
+                 name, and the names in text order form the reduced string.",
+            ),
+            Step::ReducedSa => Explanation::new(
+                "Sort the LMS suffixes via the reduced string",
+                // Only the last level does not recurse.
+                if state.level + 1 == self.levels.len() {
+                    "All names are distinct, so the suffix array of the reduced string \
+                     follows directly from the names."
+                } else {
+                    "Some names are equal, so the reduced string was sorted recursively."
+                },
+            ),
+            Step::Done => Explanation::new(
+                if state.level == 0 {
+                    "All suffixes are sorted."
+                } else {
+                    "The reduced string is sorted."
+                },
+                "Inducing from the sorted LMS suffixes sorts all suffixes.",
+            ),
+        }
+    }
+}
+
+impl Viz for SAIS {
+    fn canvas_size(&self) -> (usize, usize) {
+        canvas_size(max(self.text.len() + 4, 16), 16)
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![
+            ("small", SMALL_COLOUR),
+            ("large", LARGE_COLOUR),
+            ("default", DEFAULT),
+        ]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(&self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = &self.states[state];
+        let lvl = &self.levels[state.level];
+        let s = (0..lvl.s.len())
+            .map(|i| self.label(state.level, lvl.s[i]))
+            .collect::<Vec<_>>();
+        let reduced = (state.pass == 2 || state.step == Step::Names).then_some(&lvl.reduced);
+        let reduced_sa = (state.pass == 2).then_some(&lvl.reduced_sa);
+        // The start of the bucket of each character.
+        let buckets = if state.level == 0 {
+            let mut alph: Vec<u8> = lvl.s.iter().map(|&c| c as u8).collect();
+            alph.sort();
+            alph.dedup();
+            json!(char_map(
+                &alph,
+                alph.iter().map(|&c| lvl.buckets[c as usize])
+            ))
+        } else {
+            json!(lvl.buckets[..lvl.buckets.len() - 1])
+        };
+        json!({
+            "level": state.level,
+            "pass": state.pass,
+            "step": state.step,
+            "s": s,
+            "small": lvl.small,
+            "lms": (state.step != Step::Types).then_some(&lvl.lms),
+            "buckets": buckets,
+            "sa": state.sa,
+            "pointers": state.pointers,
+            "reduced": reduced,
+            "reduced_sa": reduced_sa,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = &self.states[state];
+        let title = self.explain(state).title;
+        let lvl = &self.levels[state.level];
+        let s = &lvl.s;
+        let n = s.len();
+        draw_background(canvas);
+
+        let type_colour = |i: usize| {
+            if lvl.small[i] {
+                SMALL_COLOUR
+            } else {
+                LARGE_COLOUR
+            }
+        };
+
+        // Positioning
+
+        // Top left of S.
+        let ps = Pos(3, 2);
+        // S/L types and LMS markers.
+        let ptypes = ps.down(1);
+        let plms = ps.down(2);
+        // Row indices, bucket characters and the SA.
+        let pj = ps.down(4);
+        let pbucket = ps.down(5);
+        let psa = ps.down(6);
+        // Bucket heads or tails.
+        let pptr = ps.down(7);
+        // The reduced string and its SA.
+        let pred = ps.down(9);
+        let plabel = Pos(0, ps.1 + 12);
+
+        if state.level > 0 {
+            draw_text(
+                Pos(0, 0),
+                &format!("Recursion level {}", state.level),
+                canvas,
+            );
+        }
+
+        // The string with types.
+        draw_label(ps.left(1).up(1), "i", canvas);
+        draw_label(ps.left(1), "S", canvas);
+        draw_label(ptypes.left(1), "t", canvas);
+        for (i, &c) in s.iter().enumerate() {
+            draw_label(ps.right(i).up(1), &i.to_string(), canvas);
+            draw_text_box(
+                ps.right(i),
+                &self.label(state.level, c),
+                type_colour(i),
+                canvas,
+            );
+            draw_label(
+                ptypes.right(i),
+                if lvl.small[i] { "S" } else { "L" },
+                canvas,
+            );
+        }
+        // LMS positions are marked, and replaced by their names once known.
+        let named = state.pass == 2 || state.step == Step::Names;
+        if state.step != Step::Types {
+            draw_label(plms.left(1), "LMS", canvas);
+            for (k, &i) in lvl.lms.iter().enumerate() {
+                let label = if named {
+                    lvl.reduced[k].to_string()
+                } else {
+                    "*".to_string()
+                };
+                draw_label(plms.right(i), &label, canvas);
+            }
+        }
+
+        // The SA, with a box around each bucket.
+        draw_label(pj.left(1), "j", canvas);
+        draw_label(psa.left(1), "SA", canvas);
+        for j in 0..n {
+            draw_label(pj.right(j), &j.to_string(), canvas);
+            draw_label(
+                pbucket.right(j),
+                &self.label(state.level, bucket_of(&lvl.buckets, j)),
+                canvas,
+            );
+            match state.sa[j] {
+                Some(i) => draw_text_box(psa.right(j), &i.to_string(), type_colour(i), canvas),
+                None => draw_text_box(psa.right(j), "-", DEFAULT, canvas),
+            }
+        }
+        for c in 0..lvl.buckets.len() - 1 {
+            let (start, end) = (lvl.buckets[c], lvl.buckets[c + 1]);
+            if start < end {
+                draw_highlight_box(psa.right(start), end - start, 1, BLACK, canvas);
+            }
+        }
+
+        // Bucket heads while inducing L, tails otherwise.
+        if !state.pointers.is_empty() {
+            let heads = matches!(state.step, Step::InduceL { .. });
+            draw_label(pptr.left(1), if heads { "head" } else { "tail" }, canvas);
+            for c in 0..lvl.buckets.len() - 1 {
+                let (start, end) = (lvl.buckets[c], lvl.buckets[c + 1]);
+                let slot = if heads {
+                    state.pointers[c]
+                } else {
+                    state.pointers[c].wrapping_sub(1)
+                };
+                // Only show pointers to free slots.
+                if start <= slot && slot < end && state.sa[slot].is_none() {
+                    draw_label(pptr.right(slot), "^", canvas);
+                }
+            }
+        }
+
+        // The reduced string and its suffix array.
+        if named {
+            draw_label(pred.left(1), "S'", canvas);
+            for (k, &c) in lvl.reduced.iter().enumerate() {
+                draw_text_box(pred.right(k), &c.to_string(), DEFAULT, canvas);
+            }
+        }
+        if state.pass == 2 {
+            draw_label(pred.left(1).down(1), "SA'", canvas);
+            for (r, &k) in lvl.reduced_sa.iter().enumerate() {
+                draw_label(pred.right(r).down(1), &k.to_string(), canvas);
+            }
+        }
+
+        // Highlights
+        match state.step {
+            Step::PlaceLms { i, row } => {
+                draw_highlight(ps.right(i), BLUE, canvas);
+                draw_highlight(psa.right(row), BLUE, canvas);
+                if state.pass == 2 {
+                    let k = lvl.lms.iter().position(|&x| x == i).unwrap();
+                    let r = lvl.reduced_sa.iter().position(|&x| x == k).unwrap();
+                    draw_highlight(plms.right(i), BLUE, canvas);
+                    draw_highlight(pred.right(r).down(1), BLUE, canvas);
+                }
+            }
+            Step::InduceL { j, i, row } | Step::InduceS { j, i, row } => {
+                draw_highlight(psa.right(j), RED, canvas);
+                draw_highlight(ps.right(i + 1), RED, canvas);
+                draw_highlight(ps.right(i), BLUE, canvas);
+                draw_highlight(ptypes.right(i), BLUE, canvas);
+                draw_highlight(psa.right(row), BLUE, canvas);
+            }
+            _ => {}
+        }
+
+        draw_text(plabel, &title, canvas);
+        true
+    }
+}
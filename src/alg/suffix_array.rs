@@ -1,94 +1,268 @@
-use crate::{alg::Viz, canvas::*};
+use serde::Serialize;
+use serde_json::{json, Value};
 
-const SMALL_COLOUR: (u8, u8, u8) = GREEN;
-const LARGE_COLOUR: (u8, u8, u8) = (244, 113, 116);
+use crate::{
+    alg::{char_map, validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
 
-#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Debug)]
+pub const SMALL_COLOUR: (u8, u8, u8) = GREEN;
+pub const LARGE_COLOUR: (u8, u8, u8) = (244, 113, 116);
+
+/// For each suffix, whether it is small: smaller than the suffix after it.
+/// The last suffix is small.
+pub fn small_suffixes<T: Ord>(s: &[T]) -> Vec<bool> {
+    let n = s.len();
+    let mut small = vec![true; n];
+    for i in (0..n.saturating_sub(1)).rev() {
+        small[i] = s[i] < s[i + 1] || (s[i] == s[i + 1] && small[i + 1]);
+    }
+    small
+}
+
+/// The suffixes starting with `c` go in `buckets[c]..buckets[c + 1]` of the suffix array.
+pub fn buckets<T: Copy + Into<usize>>(s: &[T]) -> Vec<usize> {
+    let sigma = s.iter().map(|&c| c.into()).max().map_or(0, |c| c + 1);
+    let mut buckets = vec![0; sigma + 1];
+    for &c in s {
+        buckets[c.into() + 1] += 1;
+    }
+    for c in 0..sigma {
+        buckets[c + 1] += buckets[c];
+    }
+    buckets
+}
+
+/// The character whose bucket contains row `j` of the suffix array.
+pub fn bucket_of(buckets: &[usize], j: usize) -> usize {
+    buckets.iter().rposition(|&start| start <= j).unwrap()
+}
+
+#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Debug, Serialize)]
 pub enum RowState {
     Step0,
     Step1,
     Step2,
 }
 
-#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Debug)]
+#[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Copy, Debug, Serialize)]
 pub enum State {
+    Types,
+    Distances,
+    Buckets,
+    List(usize),
+    Names,
+    Recurse,
     Init,
     Row(usize, RowState),
     End,
 }
 
-pub struct SA {
-    s: Vec<u8>,
-    states: Vec<State>,
+/// The first half of Ko-Aluru: sorting the small suffixes by reducing them to a shorter string.
+struct Reduction {
+    /// The small positions from left to right.
+    small: Vec<usize>,
+    /// For each position, the distance to the closest small position strictly before it.
+    dist: Vec<Option<usize>>,
+    /// `lists[d-1]` contains the positions at distance `d`, ordered by their character, with
+    /// large positions before small ones.
+    lists: Vec<Vec<usize>>,
+    /// `groups[d]` are the small suffixes grouped by the first `d+1` characters of their
+    /// S-substring, which runs up to and including the next small position.
+    groups: Vec<Vec<Vec<usize>>>,
+    /// The reduced string: the name of the S-substring at each small position.
+    names: Vec<usize>,
+    /// The suffix array of the reduced string, set by `SA::sort`.
+    reduced_sa: Vec<usize>,
 }
 
-impl SA {
-    pub fn new(s: Vec<u8>) -> Self {
-        let mut states = vec![State::Init];
-        for j in 0..s.len() {
-            states.extend([
-                State::Row(j, RowState::Step0),
-                State::Row(j, RowState::Step1),
-                State::Row(j, RowState::Step2),
-            ]);
+impl Reduction {
+    fn new(s: &[usize]) -> Self {
+        let n = s.len();
+        let is_small = small_suffixes(s);
+        let small: Vec<usize> = (0..n).filter(|&i| is_small[i]).collect();
+
+        let mut dist = vec![None; n];
+        let mut last = None;
+        for (i, d) in dist.iter_mut().enumerate() {
+            *d = last.map(|l| i - l);
+            if is_small[i] {
+                last = Some(i);
+            }
         }
-        states.push(State::End);
-        Self { s, states }
-    }
-}
+        let max_dist = dist.iter().flatten().copied().max().unwrap_or(0);
+        let lists: Vec<Vec<usize>> = (1..=max_dist)
+            .map(|d| {
+                let mut list: Vec<usize> = (0..n).filter(|&j| dist[j] == Some(d)).collect();
+                // Small characters sort after large ones with the same character.
+                list.sort_by_key(|&j| (s[j], is_small[j]));
+                list
+            })
+            .collect();
 
-impl Viz for SA {
-    fn canvas_size(&self) -> (usize, usize) {
-        canvas_size(self.s.len() + 4, self.s.len() + 4)
+        // Bucket the small suffixes by their first character.
+        let mut first = small.clone();
+        first.sort_by_key(|&i| s[i]);
+        let mut groups: Vec<Vec<Vec<usize>>> = vec![first
+            .chunk_by(|&i, &k| s[i] == s[k])
+            .map(|group| group.to_vec())
+            .collect()];
+        for (d, list) in (1..).zip(&lists) {
+            let mut refined = groups.last().unwrap().clone();
+            for run in list.chunk_by(|&j, &k| (s[j], is_small[j]) == (s[k], is_small[k])) {
+                // Move the suffixes to the front of their group, and split them off.
+                refined = refined
+                    .into_iter()
+                    .flat_map(|group| {
+                        let moved: Vec<usize> = run
+                            .iter()
+                            .map(|&j| j - d)
+                            .filter(|i| group.contains(i))
+                            .collect();
+                        let rest: Vec<usize> =
+                            group.into_iter().filter(|i| !moved.contains(i)).collect();
+                        [moved, rest].into_iter().filter(|part| !part.is_empty())
+                    })
+                    .collect();
+            }
+            groups.push(refined);
+        }
+
+        let name_of = |i: usize| {
+            groups[max_dist]
+                .iter()
+                .position(|group| group.contains(&i))
+                .unwrap()
+        };
+        let names: Vec<usize> = small.iter().map(|&i| name_of(i)).collect();
+        Self {
+            small,
+            dist,
+            lists,
+            groups,
+            names,
+            reduced_sa: vec![],
+        }
     }
 
-    fn num_states(&self) -> usize {
-        self.states.len()
+    /// Whether all S-substrings are different, so that the reduced string needs no recursion.
+    fn distinct(&self) -> bool {
+        self.groups.last().unwrap().len() == self.names.len()
     }
 
-    fn draw(&self, state: usize, canvas: &mut Box<dyn Canvas>) -> bool {
-        let state = self.states[state];
-        canvas.fill_background(WHITE);
+    /// The groups of small suffixes as shown in the given state.
+    fn groups(&self, state: State) -> Option<&Vec<Vec<usize>>> {
+        match state {
+            State::Buckets => self.groups.first(),
+            State::List(d) => Some(&self.groups[d]),
+            State::Names | State::Recurse => self.groups.last(),
+            _ => None,
+        }
+    }
+}
 
-        let s = &self.s;
-        let n = s.len();
+/// The string at one level of the recursion.
+struct Level {
+    s: Vec<usize>,
+    reduction: Reduction,
+}
 
-        let is_small = |i| i == n - 1 || s[i..] < s[i + 1..];
-        let is_small_color = |i| {
-            if is_small(i) {
-                SMALL_COLOUR
-            } else {
-                LARGE_COLOUR
-            }
-        };
+impl Level {
+    fn suffix_array(&self) -> Vec<usize> {
+        let mut sa: Vec<usize> = (0..self.s.len()).collect();
+        sa.sort_by_key(|&i| &self.s[i..]);
+        sa
+    }
+}
 
-        // Positioning
+pub struct SA {
+    levels: Vec<Level>,
+    /// The recursion level of each state, and the state.
+    states: Vec<(usize, State)>,
+}
 
-        // Top left of SA.
-        let psa = Pos(3, 3);
-        // First entry of j column
-        let cj = psa.left(3);
-        // First entry of SA column
-        let csa = psa.left(2);
+impl SA {
+    /// With `full`, first show how the small suffixes are sorted recursively,
+    /// instead of starting with them already sorted.
+    pub fn new(s: Vec<u8>, full: bool) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let mut sa = Self {
+            levels: vec![],
+            states: vec![],
+        };
+        sa.sort(s.iter().map(|&c| c as usize).collect(), full);
+        Ok(sa)
+    }
 
-        // Top left of S at the top.
-        let ps = Pos(3, 1);
-        // The first label of S.
-        let ri = ps.up(1);
+    /// Add the states that sort `s` at the next level, recursing on the reduced string when
+    /// its names are not distinct.
+    fn sort(&mut self, s: Vec<usize>, full: bool) {
+        let level = self.levels.len();
+        let n = s.len();
+        let reduction = Reduction::new(&s);
+        let lists = reduction.lists.len();
+        self.levels.push(Level { s, reduction });
+        let states = &mut self.states;
+        if full {
+            states.extend([State::Types, State::Distances, State::Buckets].map(|st| (level, st)));
+            states.extend((1..=lists).map(|d| (level, State::List(d))));
+            states.push((level, State::Names));
+        }
 
-        // Draw the string at the top.
-        draw_string_with_labels(ps, s, is_small_color, canvas);
+        let r = &self.levels[level].reduction;
+        let reduced_sa = if r.distinct() {
+            // Distinct names are the ranks of the suffixes of the reduced string.
+            let mut reduced_sa = vec![0; r.names.len()];
+            for (k, &name) in r.names.iter().enumerate() {
+                reduced_sa[name] = k;
+            }
+            reduced_sa
+        } else if full {
+            let names = r.names.clone();
+            self.sort(names, true);
+            self.levels[level + 1].suffix_array()
+        } else {
+            vec![]
+        };
+        self.levels[level].reduction.reduced_sa = reduced_sa;
 
-        let mut buckets = [0 as usize; 256];
-        for &c in s.iter() {
-            buckets[c as usize] += 1;
+        let states = &mut self.states;
+        if full {
+            states.push((level, State::Recurse));
         }
-        for i in 0..254 {
-            buckets[i + 1] += buckets[i];
+        states.push((level, State::Init));
+        for j in 0..n {
+            states.extend([
+                (level, State::Row(j, RowState::Step0)),
+                (level, State::Row(j, RowState::Step1)),
+                (level, State::Row(j, RowState::Step2)),
+            ]);
         }
+        states.push((level, State::End));
+    }
 
-        let mut final_sa: Vec<usize> = (0..n).collect();
-        final_sa.sort_by_key(|&i| &s[i..]);
+    fn label(level: usize, c: usize) -> String {
+        if level == 0 {
+            to_label(c as u8)
+        } else {
+            c.to_string()
+        }
+    }
+
+    /// The name of the string at the given level: S, S', S'', ...
+    fn name(level: usize) -> String {
+        format!("S{}", "'".repeat(level))
+    }
+
+    /// The suffix array as shown in the given state, with `None` for holes,
+    /// and the row the current large suffix is inserted at.
+    fn partial_sa(&self, level: usize, state: State) -> (Vec<Option<usize>>, usize) {
+        let s = &self.levels[level].s;
+        let n = s.len();
+        let small = small_suffixes(s);
+        let is_small = |i: usize| small[i];
+        let buckets = buckets(s);
+        let final_sa = self.levels[level].suffix_array();
 
         let mut sa = vec![None; n];
         for j in 0..n {
@@ -100,7 +274,6 @@ impl Viz for SA {
         // Compute the SA as far as needed
         let mut new_j = 0;
         match state {
-            State::Init => {}
             State::Row(rj, _) => {
                 for j in 0..=rj {
                     let i = sa[j].unwrap();
@@ -108,13 +281,14 @@ impl Viz for SA {
                         continue;
                     }
                     let c = s[i - 1];
-                    new_j = (buckets[c as usize - 1]..buckets[c as usize])
+                    new_j = (buckets[c]..buckets[c + 1])
                         .find(|&j| sa[j].is_none())
                         .unwrap();
                     sa[new_j] = Some(i - 1);
                 }
             }
             State::End => sa = final_sa.iter().map(|&i| Some(i)).collect(),
+            _ => {}
         }
 
         if let State::Row(j, rs) = state {
@@ -125,6 +299,392 @@ impl Viz for SA {
                 sa[new_j] = None;
             }
         }
+        (sa, new_j)
+    }
+
+    /// The row of the reduced string, below the distance lists.
+    fn reduced_row(r: &Reduction) -> usize {
+        8 + r.lists.len()
+    }
+
+    /// The number of rows of the canvas.
+    fn rows(&self) -> usize {
+        let n = self.levels[0].s.len();
+        if self.states[0].1 == State::Types {
+            self.levels
+                .iter()
+                .map(|level| Self::reduced_row(&level.reduction) + 4)
+                .fold(n + 4, usize::max)
+        } else {
+            n + 4
+        }
+    }
+
+    /// Draw the states that sort the small suffixes, below the string at the top.
+    fn draw_reduction(&self, level: usize, state: State, canvas: &mut CanvasBox) {
+        let r = &self.levels[level].reduction;
+        let ps = Pos(3, 1);
+        // Distances, below the string.
+        let pd = ps.down(1);
+        // Groups of small suffixes.
+        let pg = Pos(3, 4);
+        // The distance lists.
+        let pl = Pos(3, 7);
+        // The reduced string.
+        let pr = Pos(3, Self::reduced_row(r));
+
+        if state >= State::Distances {
+            draw_label(pd.left(1), "d", canvas);
+            for (i, d) in r.dist.iter().enumerate() {
+                if let Some(d) = d {
+                    draw_label(pd.right(i), &d.to_string(), canvas);
+                }
+            }
+            draw_label(pl.left(1).up(1), "d", canvas);
+            for (d, list) in r.lists.iter().enumerate() {
+                draw_label(pl.left(1).down(d), &(d + 1).to_string(), canvas);
+                for (k, &j) in list.iter().enumerate() {
+                    draw_text_box(pl.down(d).right(k), &j.to_string(), WHITE, canvas);
+                }
+            }
+        }
+
+        if let Some(groups) = r.groups(state) {
+            draw_label(pg.left(1), "sort", canvas);
+            let mut k = 0;
+            for (name, group) in groups.iter().enumerate() {
+                for (k2, &i) in group.iter().enumerate() {
+                    draw_text_box(pg.right(k + k2), &i.to_string(), SMALL_COLOUR, canvas);
+                }
+                if k > 0 {
+                    draw_highlight_box(pg.right(k), 0, 1, BLACK, canvas);
+                }
+                if state >= State::Names {
+                    draw_label(pg.down(1).right(k), &name.to_string(), canvas);
+                }
+                k += group.len();
+            }
+        }
+
+        match state {
+            State::List(d) => {
+                draw_highlight_box(pl.down(d - 1), r.lists[d - 1].len(), 1, RED, canvas);
+                let order: Vec<usize> = r.groups[d].iter().flatten().copied().collect();
+                for &j in &r.lists[d - 1] {
+                    draw_highlight(ps.right(j), BLUE, canvas);
+                    draw_highlight(ps.right(j - d), RED, canvas);
+                    let k = order.iter().position(|&i| i == j - d).unwrap();
+                    draw_highlight(pg.right(k), RED, canvas);
+                }
+            }
+            State::Names | State::Recurse => {
+                draw_label(pr.left(1), &Self::name(level + 1), canvas);
+                for (k, (&i, &name)) in r.small.iter().zip(&r.names).enumerate() {
+                    draw_text_box(pr.right(k), &name.to_string(), SMALL_COLOUR, canvas);
+                    draw_label(pr.up(1).right(k), &i.to_string(), canvas);
+                }
+                if state == State::Recurse {
+                    draw_label(
+                        pr.left(1).down(1),
+                        &format!("SA{}", "'".repeat(level + 1)),
+                        canvas,
+                    );
+                    for (k, &k2) in r.reduced_sa.iter().enumerate() {
+                        draw_label(pr.down(1).right(k), &k2.to_string(), canvas);
+                        let i = r.small[k2];
+                        draw_text_box(pr.down(2).right(k), &i.to_string(), SMALL_COLOUR, canvas);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn explain(&self, level: usize, state: State) -> Explanation {
+        let s = &self.levels[level].s;
+        let small = small_suffixes(s);
+        let is_small = |i: usize| small[i];
+
+        let State::Row(j, rs) = state else {
+            let r = &self.levels[level].reduction;
+            let (name, reduced) = (Self::name(level), Self::name(level + 1));
+            return match state {
+                State::Types if level > 0 => Explanation::new(
+                    format!("Recurse on {name}."),
+                    format!(
+                        "The suffixes of {name} are sorted with the same algorithm. Scanning \
+                         from right to left, a suffix is small (green) when it is smaller than \
+                         the suffix after it, and large (red) otherwise."
+                    ),
+                ),
+                State::Types => Explanation::new(
+                    "Classify the suffixes.",
+                    "Scanning from right to left, a suffix is small (green) when it is smaller \
+                     than the suffix after it, and large (red) otherwise. \
+                     Ko-Aluru first sorts the small suffixes, and then places the large ones.",
+                ),
+                State::Distances => Explanation::new(
+                    "Distance to the previous small position.",
+                    "Each position gets its distance to the closest small position before it. \
+                     Positions with distance d are collected in list d, \
+                     ordered by their character, with large positions first.",
+                ),
+                State::Buckets => Explanation::new(
+                    "Bucket the small suffixes.",
+                    "The S-substring of a small suffix runs up to and including the next small \
+                     position. Group the small suffixes by their first character.",
+                ),
+                State::List(d) => Explanation::new(
+                    format!("Refine using list {d}"),
+                    format!(
+                        "For each position j in list {d}, in order, move suffix j-{d} to the \
+                         front of its group, after the suffixes moved before. The suffixes moved \
+                         for the same character and type split off as a new group. Now the small \
+                         suffixes are grouped by the first {} characters of their S-substring.",
+                        d + 1
+                    ),
+                ),
+                State::Names => Explanation::new(
+                    "Name the S-substrings.",
+                    format!(
+                        "Each group gets the rank of its S-substring as name. Writing the names \
+                         in text order gives the reduced string {reduced} of length {}.",
+                        r.names.len()
+                    ),
+                ),
+                State::Recurse if r.distinct() => Explanation::new(
+                    "Sort the reduced string.",
+                    format!(
+                        "All names are distinct, so the suffix array of {reduced} follows \
+                         directly. Sorting the suffixes of {reduced} sorts the small suffixes of \
+                         {name}."
+                    ),
+                ),
+                State::Recurse => Explanation::new(
+                    "The reduced string is sorted recursively.",
+                    format!(
+                        "Some names are equal, so the suffix array of {reduced} was computed by \
+                         running the same algorithm on it, at recursion level {}. Sorting the \
+                         suffixes of {reduced} sorts the small suffixes of {name}.",
+                        level + 1
+                    ),
+                ),
+                State::Init => Explanation::new(
+                    "Small suffixes are sorted.",
+                    "Small suffixes (green) are smaller than the suffix after them. \
+                     They are already sorted and placed at the end of the bucket of their \
+                     first character. The large suffixes (red) still need to be placed.",
+                ),
+                _ if level > 0 => Explanation::new(
+                    format!("The suffixes of {name} are sorted."),
+                    format!(
+                        "Every large suffix was placed by the suffix after it, so the suffix \
+                         array of {name} is complete. Back at recursion level {}, it gives the \
+                         order of the small suffixes.",
+                        level - 1
+                    ),
+                ),
+                _ => Explanation::new(
+                    "All suffixes are sorted.",
+                    "Every large suffix was placed by the suffix after it, \
+                     so the suffix array is complete.",
+                ),
+            };
+        };
+
+        let i = self.levels[level].suffix_array()[j];
+        if i == 0 {
+            return Explanation::new(
+                format!("Visit SA[{j}] = {i}"),
+                "There is no suffix before suffix 0, so there is nothing to place.",
+            );
+        }
+        if is_small(i - 1) {
+            return Explanation::new(
+                format!("Visit SA[{j}] = {i}"),
+                format!("Suffix {} before it is small and already placed.", i - 1),
+            );
+        }
+        let c = Self::label(level, s[i - 1]);
+        let name = Self::name(level);
+        match rs {
+            RowState::Step0 => Explanation::new(
+                format!("Visit SA[{j}] = {i}"),
+                format!(
+                    "Scan the suffix array from top to bottom. \
+                     Suffix {} before it is large, so it is larger than suffix {i}.",
+                    i - 1
+                ),
+            ),
+            RowState::Step1 => Explanation::new(
+                format!("Suffix {} goes in bucket {c}", i - 1),
+                format!(
+                    "Large suffixes in a bucket are placed in the order their successors are \
+                     visited, so suffix {} takes the first free slot in the bucket of \
+                     {name}[{}] = {c}.",
+                    i - 1,
+                    i - 1
+                ),
+            ),
+            RowState::Step2 => Explanation::new(
+                format!("Insert suffix {}", i - 1),
+                format!("Suffix {} is now at its final position.", i - 1),
+            ),
+        }
+    }
+}
+
+impl Viz for SA {
+    fn canvas_size(&self) -> (usize, usize) {
+        canvas_size(self.levels[0].s.len() + 4, self.rows())
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("small", SMALL_COLOUR), ("large", LARGE_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        let (level, state) = self.states[state];
+        self.explain(level, state)
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let (level, state) = self.states[state];
+        let s = &self.levels[level].s;
+        let n = s.len();
+        let small = small_suffixes(s);
+        let is_small = |i: usize| small[i];
+
+        let buckets = buckets(s);
+        let (string, bucket_start) = if level == 0 {
+            let s: Vec<u8> = s.iter().map(|&c| c as u8).collect();
+            let mut alph = s.clone();
+            alph.sort();
+            alph.dedup();
+            (
+                json!(String::from_utf8_lossy(&s)),
+                json!(char_map(&alph, alph.iter().map(|&c| buckets[c as usize]))),
+            )
+        } else {
+            (json!(s), json!(buckets[..buckets.len() - 1]))
+        };
+        let final_sa = self.levels[level].suffix_array();
+        let (sa, new_j) = self.partial_sa(level, state);
+
+        let highlight = match state {
+            State::Row(j, rs) => {
+                let i = sa[j].unwrap();
+                let skip = i == 0 || is_small(i - 1);
+                json!({
+                    "row": j,
+                    "suffix": i,
+                    "target_row": (rs > RowState::Step0 && !skip).then_some(new_j),
+                })
+            }
+            _ => Value::Null,
+        };
+        let r = &self.levels[level].reduction;
+        let reduction = if self.states[0].1 == State::Types {
+            json!({
+                "dist": (state >= State::Distances).then_some(&r.dist),
+                "lists": (state >= State::Distances).then_some(&r.lists),
+                "groups": r.groups(state),
+                "small": r.small,
+                "names": (state >= State::Names).then_some(&r.names),
+                "reduced_sa": (state >= State::Recurse).then_some(&r.reduced_sa),
+            })
+        } else {
+            Value::Null
+        };
+        let highlight = match state {
+            State::List(d) => json!({ "list": d }),
+            _ => highlight,
+        };
+        json!({
+            "level": level,
+            "state": state,
+            "s": string,
+            "reduction": reduction,
+            "small": (0..n).map(is_small).collect::<Vec<_>>(),
+            "bucket_start": bucket_start,
+            "sa": final_sa,
+            "partial_sa": (state >= State::Init).then_some(sa),
+            "highlight": highlight,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut Box<dyn Canvas>) -> bool {
+        let (level, state) = self.states[state];
+        canvas.fill_background(WHITE);
+
+        let s = &self.levels[level].s;
+        let n = s.len();
+        let label = |c: usize| Self::label(level, c);
+
+        let small = small_suffixes(s);
+        let is_small = |i: usize| small[i];
+        let is_small_color = |i| {
+            if is_small(i) {
+                SMALL_COLOUR
+            } else {
+                LARGE_COLOUR
+            }
+        };
+
+        // Positioning
+
+        // Top left of SA.
+        let psa = Pos(3, 3);
+        // First entry of j column
+        let cj = psa.left(3);
+        // First entry of SA column
+        let csa = psa.left(2);
+
+        // Top left of S at the top.
+        let ps = Pos(3, 1);
+        // The first label of S.
+        let ri = ps.up(1);
+
+        if level > 0 {
+            draw_text(
+                Pos(0, self.rows() - 1),
+                &format!("Recursion level {level}"),
+                canvas,
+            );
+        }
+
+        // Draw the string at the top.
+        draw_label(ri.left(1), "i", canvas);
+        for i in 0..n {
+            draw_label(ri.right(i), &i.to_string(), canvas);
+        }
+        draw_label(ps.left(1), &Self::name(level), canvas);
+        for (i, &c) in s.iter().enumerate() {
+            let color = if state == State::Types {
+                WHITE
+            } else {
+                is_small_color(i)
+            };
+            draw_text_box(ps.right(i), &label(c), color, canvas);
+        }
+        if state < State::Init {
+            if state == State::Types {
+                draw_label(ps.left(1).down(1), "t", canvas);
+                for i in 0..n {
+                    let t = if is_small(i) { "S" } else { "L" };
+                    draw_text_box(ps.down(1).right(i), t, is_small_color(i), canvas);
+                }
+            }
+            self.draw_reduction(level, state, canvas);
+            return true;
+        }
+
+        let (sa, new_j) = self.partial_sa(level, state);
+        let buckets = buckets(s);
 
         // Draw the SA
         draw_label(cj.up(1), "j", canvas);
@@ -133,17 +693,19 @@ impl Viz for SA {
             draw_label(cj.down(j), &j.to_string(), canvas);
             if let Some(i) = sa[j] {
                 draw_label(csa.down(j), &i.to_string(), canvas);
-                draw_string(psa.down(j), &s[i..], |i2| is_small_color(i + i2), canvas);
+                for (i2, &c) in s[i..].iter().enumerate() {
+                    draw_text_box(
+                        psa.down(j).right(i2),
+                        &label(c),
+                        is_small_color(i + i2),
+                        canvas,
+                    );
+                }
             } else {
                 // Find the first letter for this bucket
-                let bucket = buckets
-                    .iter()
-                    .enumerate()
-                    .find(|&(_, &cnt)| cnt > j)
-                    .unwrap()
-                    .0 as u8;
+                let bucket = bucket_of(&buckets, j);
                 draw_label(csa.down(j), &'-'.to_string(), canvas);
-                draw_char_box(psa.down(j), bucket, LARGE_COLOUR, canvas);
+                draw_text_box(psa.down(j), &label(bucket), LARGE_COLOUR, canvas);
             }
         }
 
@@ -0,0 +1,544 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{cmp::max, collections::BTreeMap};
+
+use crate::{
+    alg::{ukkonen::LINK_COLOUR, validate_query, validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+const CLONE_COLOUR: Color = (255, 200, 130);
+const OCC_COLOUR: Color = (170, 230, 170);
+
+#[derive(Clone)]
+struct Node {
+    /// The length of the longest string of this state.
+    len: usize,
+    /// The suffix link: the state of the longest suffix in a different class.
+    link: Option<usize>,
+    next: BTreeMap<u8, usize>,
+    clone: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum Step {
+    // Add the state `cur` for the prefix ending in character `i`.
+    Add { cur: usize },
+    // Add the transition from `p` to `cur`, and follow the suffix link of `p`.
+    Transition { p: usize, cur: usize },
+    // No state on the suffix link path has a transition: `cur` links to the root.
+    LinkRoot { cur: usize },
+    // `p` has a transition to `q` with `len(q) = len(p) + 1`: `cur` links to `q`.
+    Link { p: usize, q: usize, cur: usize },
+    // `p` has a transition to a longer `q`: copy it into `clone` with `len(p) + 1`.
+    Clone { p: usize, q: usize, clone: usize },
+    // Redirect the transition of `p` from `q` to `clone`.
+    Redirect { p: usize, q: usize, clone: usize },
+    // Link both `q` and `cur` to `clone`.
+    CloneLinks { q: usize, clone: usize, cur: usize },
+    Done,
+    // Read `k` characters of the query, forward in the automaton and backward in the BWT.
+    Query { k: usize },
+}
+
+#[derive(Clone)]
+pub struct State {
+    /// The character of S being added.
+    i: usize,
+    step: Step,
+    nodes: Vec<Node>,
+}
+
+pub struct SuffixAutomaton {
+    s: Vec<u8>,
+    q: Vec<u8>,
+    alph: Vec<u8>,
+    states: Vec<State>,
+    /// The end positions of the strings of each state of the complete automaton.
+    endpos: Vec<Vec<usize>>,
+    /// The state after reading the first `k` characters of the query, if any.
+    forward: Vec<Option<usize>>,
+    /// The range of rows of the suffix array starting with the last `k` characters of the query.
+    backward: Vec<(usize, usize)>,
+    /// Where each state is drawn, as (column, row) relative to the root.
+    pos: Vec<(usize, usize)>,
+    clone_rows: usize,
+}
+
+impl SuffixAutomaton {
+    pub fn new(s: Vec<u8>, q: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        validate_query(&s, &q, s.len())?;
+        let n = s.len();
+        let alph = {
+            let mut alph = s.clone();
+            alph.sort();
+            alph.dedup();
+            alph
+        };
+
+        let mut nodes = vec![Node {
+            len: 0,
+            link: None,
+            next: BTreeMap::new(),
+            clone: false,
+        }];
+        let mut states = vec![];
+        let mut last = 0;
+        for (i, &c) in s.iter().enumerate() {
+            let mut push = |step, nodes: &Vec<Node>| {
+                states.push(State {
+                    i,
+                    step,
+                    nodes: nodes.clone(),
+                })
+            };
+            let cur = nodes.len();
+            nodes.push(Node {
+                len: nodes[last].len + 1,
+                link: None,
+                next: BTreeMap::new(),
+                clone: false,
+            });
+            push(Step::Add { cur }, &nodes);
+            let mut p = Some(last);
+            while let Some(u) = p {
+                if nodes[u].next.contains_key(&c) {
+                    break;
+                }
+                nodes[u].next.insert(c, cur);
+                push(Step::Transition { p: u, cur }, &nodes);
+                p = nodes[u].link;
+            }
+            match p {
+                None => {
+                    nodes[cur].link = Some(0);
+                    push(Step::LinkRoot { cur }, &nodes);
+                }
+                Some(p) => {
+                    let q = nodes[p].next[&c];
+                    if nodes[p].len + 1 == nodes[q].len {
+                        nodes[cur].link = Some(q);
+                        push(Step::Link { p, q, cur }, &nodes);
+                    } else {
+                        let clone = nodes.len();
+                        nodes.push(Node {
+                            len: nodes[p].len + 1,
+                            clone: true,
+                            ..nodes[q].clone()
+                        });
+                        push(Step::Clone { p, q, clone }, &nodes);
+                        let mut p = Some(p);
+                        while let Some(u) = p {
+                            if nodes[u].next.get(&c) != Some(&q) {
+                                break;
+                            }
+                            nodes[u].next.insert(c, clone);
+                            push(Step::Redirect { p: u, q, clone }, &nodes);
+                            p = nodes[u].link;
+                        }
+                        nodes[q].link = Some(clone);
+                        nodes[cur].link = Some(clone);
+                        push(Step::CloneLinks { q, clone, cur }, &nodes);
+                    }
+                }
+            }
+            last = cur;
+        }
+        states.push(State {
+            i: n,
+            step: Step::Done,
+            nodes: nodes.clone(),
+        });
+
+        // Each prefix ends at its own position, as do all its suffixes on the suffix link path.
+        let mut endpos = vec![vec![]; nodes.len()];
+        for i in 0..n {
+            let mut v = (0..=i).fold(0, |v, j| nodes[v].next[&s[j]]);
+            loop {
+                endpos[v].push(i);
+                match nodes[v].link {
+                    Some(link) => v = link,
+                    None => break,
+                }
+            }
+        }
+        for e in &mut endpos {
+            e.sort();
+        }
+
+        let forward = (0..=q.len())
+            .scan(Some(0), |v, k| {
+                if k > 0 {
+                    *v = v.and_then(|v| nodes[v].next.get(&q[k - 1]).copied());
+                }
+                Some(*v)
+            })
+            .collect();
+        let backward = (0..=q.len())
+            .map(|k| {
+                let pattern = &q[q.len() - k..];
+                let start = (0..n).filter(|&i| &s[i..] < pattern).count();
+                let count = (0..n).filter(|&i| s[i..].starts_with(pattern)).count();
+                (start, start + count)
+            })
+            .collect();
+        for k in 0..=q.len() {
+            states.push(State {
+                i: n,
+                step: Step::Query { k },
+                nodes: nodes.clone(),
+            });
+        }
+
+        // States are drawn in the column of their `len`. Clones go below the prefixes.
+        let mut rows = vec![0; n + 1];
+        let pos = nodes
+            .iter()
+            .map(|v| {
+                let row = rows[v.len];
+                rows[v.len] += 1;
+                (3 * v.len, 2 * row)
+            })
+            .collect();
+        let clone_rows = rows.iter().max().unwrap() - 1;
+
+        Ok(Self {
+            s,
+            q,
+            alph,
+            states,
+            endpos,
+            forward,
+            backward,
+            pos,
+            clone_rows,
+        })
+    }
+
+    /// The rows of the drawing of the automaton, with room for the curves around it.
+    fn graph_rows(&self) -> usize {
+        let n = self.s.len();
+        n / 2 + 1 + max(2 * self.clone_rows, n / 2) + 2
+    }
+
+    fn explain(&self, state: &State) -> Explanation {
+        let s = &self.s;
+        let i = state.i;
+        let len = |v: usize| state.nodes[v].len;
+        let c = s.get(i).map_or(String::new(), |&c| to_label(c));
+        match state.step {
+            Step::Add { cur } => Explanation::new(
+                format!("Add {c}: new state {cur}"),
+                format!(
+                    "The prefix of length {} ends only at position {i}, so it gets a new \
+                     state with len {}. Walking the suffix link path from the previous last \
+                     state, states without a transition on {c} get one to state {cur}.",
+                    i + 1,
+                    len(cur)
+                ),
+            ),
+            Step::Transition { p, cur } => Explanation::new(
+                format!("δ({p}, {c}) = {cur}"),
+                format!(
+                    "State {p} has no transition on {c}, so its strings followed by {c} occur \
+                     only at the end. Follow the suffix link of {p}."
+                ),
+            ),
+            Step::LinkRoot { cur } => Explanation::new(
+                format!("link({cur}) = 0"),
+                format!(
+                    "The suffix link path ended without a transition on {c}, so {c} did not \
+                     occur before and only the empty suffix occurs elsewhere."
+                ),
+            ),
+            Step::Link { p, q, cur } => Explanation::new(
+                format!("link({cur}) = {q}"),
+                format!(
+                    "State {p} has a transition on {c} to state {q}, and len({p}) + 1 = \
+                     len({q}) = {}, so the strings of {q} are exactly the longest suffixes that \
+                     occurred before.",
+                    len(q)
+                ),
+            ),
+            Step::Clone { p, q, clone } => Explanation::new(
+                format!("Clone state {q} into {clone}"),
+                format!(
+                    "State {p} has a transition on {c} to state {q}, but len({p}) + 1 = {} < \
+                     len({q}) = {}. Only the strings of {q} up to length {} now also end here, \
+                     so they are split off into a clone with the same transitions and link.",
+                    len(p) + 1,
+                    len(q),
+                    len(clone)
+                ),
+            ),
+            Step::Redirect { p, q, clone } => Explanation::new(
+                format!("δ({p}, {c}) = {clone}"),
+                format!(
+                    "State {p} has a transition on {c} to state {q}, which now belongs to the \
+                     shorter strings of the clone. Continue along the suffix link."
+                ),
+            ),
+            Step::CloneLinks { q, clone, cur } => Explanation::new(
+                format!("link({q}) = link({cur}) = {clone}"),
+                format!(
+                    "The strings of clone {clone} are the longest suffixes of both state {q} and \
+                     the new state {cur} that are in a different class."
+                ),
+            ),
+            Step::Done => Explanation::new(
+                "The suffix automaton is complete.",
+                format!(
+                    "It has {} states, at most 2n - 1, and {} transitions, at most 3n - 4. \
+                     Every substring of S is read along a path from the root.",
+                    state.nodes.len(),
+                    state.nodes.iter().map(|v| v.next.len()).sum::<usize>()
+                ),
+            ),
+            Step::Query { k } => {
+                let ql = self.q.len();
+                let (start, end) = self.backward[k];
+                let forward = match self.forward[k] {
+                    Some(v) => format!(
+                        "The automaton reads Q[..{k}] forward to state {v}, which ends at {} \
+                         positions.",
+                        self.endpos[v].len()
+                    ),
+                    None => format!(
+                        "The automaton has no transition on Q[{}], so the query does not occur.",
+                        self.forward.iter().position(|v| v.is_none()).unwrap() - 1
+                    ),
+                };
+                let backward = format!(
+                    "Backward search in the BWT reads Q[{}..] and has {} rows in [{start}, {end}).",
+                    ql - k,
+                    end - start
+                );
+                let title = match self.forward[k] {
+                    Some(v) => format!("Read {k}: state {v} and rows [{start}, {end})"),
+                    None => format!("Read {k}: no state and rows [{start}, {end})"),
+                };
+                let both = if k == ql {
+                    " Both count the same occurrences: the automaton by their end positions, \
+                     the BWT by the suffixes they start."
+                } else {
+                    ""
+                };
+                Explanation::new(title, format!("{forward} {backward}{both}"))
+            }
+        }
+    }
+}
+
+impl Viz for SuffixAutomaton {
+    fn canvas_size(&self) -> (usize, usize) {
+        let n = self.s.len();
+        let sigma = self.alph.len();
+        let num_nodes = self.pos.len();
+        canvas_size(
+            max(sigma + 5 + 3 * n + 2, n + 2),
+            3 + max(num_nodes + 1, self.graph_rows()) + 7,
+        )
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![
+            ("clone", CLONE_COLOUR),
+            ("link", LINK_COLOUR),
+            ("occurrence", OCC_COLOUR),
+        ]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(&self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = &self.states[state];
+        let nodes: Vec<Value> = state
+            .nodes
+            .iter()
+            .map(|v| {
+                let next: Map<String, Value> = v
+                    .next
+                    .iter()
+                    .map(|(&c, &w)| (to_label(c), json!(w)))
+                    .collect();
+                json!({
+                    "len": v.len,
+                    "link": v.link,
+                    "next": next,
+                    "clone": v.clone,
+                })
+            })
+            .collect();
+        let query = match state.step {
+            Step::Query { k } => json!({
+                "k": k,
+                "state": self.forward[k],
+                "endpos": self.forward[k].map(|v| &self.endpos[v]),
+                "range": self.backward[k],
+            }),
+            _ => Value::Null,
+        };
+        json!({
+            "i": state.i,
+            "step": state.step,
+            "s": String::from_utf8_lossy(&self.s),
+            "q": String::from_utf8_lossy(&self.q),
+            "nodes": nodes,
+            "query": query,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = &self.states[state];
+        let title = self.explain(state).title;
+        let s = &self.s;
+        let q = &self.q;
+        let n = s.len();
+        let sigma = self.alph.len();
+        draw_background(canvas);
+
+        // Positioning
+
+        // Top left of S at the top.
+        let ps = Pos(1, 1);
+        // The transition table: state, len, link, and a column per character.
+        let pv = Pos(0, 3);
+        let plen = pv.right(1);
+        let plink = pv.right(2);
+        let pnext = pv.right(3);
+        // The root of the automaton, with room for the suffix links above.
+        let proot = pnext.right(sigma + 1).down(n / 2 + 1);
+        let plabel = Pos(0, pv.1 + max(self.pos.len() + 1, self.graph_rows()) + 1);
+        let pquery = plabel.down(2).right(1);
+        let pquery_r = pquery.down(1);
+
+        let (occ, k) = match state.step {
+            Step::Query { k } => (self.forward[k].map(|v| &self.endpos[v]), k),
+            _ => (None, 0),
+        };
+        let is_occ = |i: usize| occ.is_some_and(|e| e.iter().any(|&e| e + 1 - k <= i && i <= e));
+        draw_string_with_labels(
+            ps,
+            s,
+            |i| if is_occ(i) { OCC_COLOUR } else { WHITE },
+            canvas,
+        );
+        if state.i < n {
+            draw_highlight(ps.right(state.i), RED, canvas);
+        }
+        draw_text(plabel, &title, canvas);
+
+        // The table.
+        draw_label(pv, "v", canvas);
+        draw_label(plen, "len", canvas);
+        draw_label(plink, "link", canvas);
+        for (ci, &c) in self.alph.iter().enumerate() {
+            draw_label(pnext.right(ci), &to_label(c), canvas);
+        }
+        for (v, node) in state.nodes.iter().enumerate() {
+            let row = v + 1;
+            draw_label(pv.down(row), &v.to_string(), canvas);
+            draw_label(plen.down(row), &node.len.to_string(), canvas);
+            if let Some(link) = node.link {
+                draw_label(plink.down(row), &link.to_string(), canvas);
+            }
+            for (ci, c) in self.alph.iter().enumerate() {
+                if let Some(w) = node.next.get(c) {
+                    draw_label(pnext.right(ci).down(row), &w.to_string(), canvas);
+                }
+            }
+        }
+
+        // The automaton.
+        let at = |v: usize| {
+            let (x, row) = self.pos[v];
+            proot.right(x).down(row)
+        };
+        // The transition that changed in this state.
+        let changed = match state.step {
+            Step::Transition { p, .. } | Step::Redirect { p, .. } => Some(p),
+            _ => None,
+        };
+        for (v, node) in state.nodes.iter().enumerate() {
+            for (&c, &w) in &node.next {
+                let colour = if changed == Some(v) && state.i < n && c == s[state.i] {
+                    RED
+                } else {
+                    BLACK
+                };
+                draw_labelled_link(at(v), at(w), &to_label(c), colour, canvas);
+            }
+            if let Some(link) = node.link {
+                draw_link(at(v), at(link), LINK_COLOUR, canvas);
+            }
+        }
+        for (v, node) in state.nodes.iter().enumerate() {
+            let colour = if node.clone { CLONE_COLOUR } else { WHITE };
+            draw_node(at(v), &v.to_string(), colour, canvas);
+        }
+
+        let mut highlight = |v: usize, colour: Color| {
+            draw_highlight(at(v), colour, canvas);
+            draw_highlight_box(pv.down(v + 1), 3 + sigma, 1, colour, canvas);
+        };
+        match state.step {
+            Step::Add { cur } | Step::LinkRoot { cur } => highlight(cur, BLUE),
+            Step::Transition { p, cur } => {
+                highlight(cur, BLUE);
+                highlight(p, RED);
+            }
+            Step::Link { p, q, cur } => {
+                highlight(cur, BLUE);
+                highlight(p, RED);
+                highlight(q, GREEN);
+            }
+            Step::Clone { p, q, clone } | Step::Redirect { p, q, clone } => {
+                highlight(p, RED);
+                highlight(q, GREEN);
+                highlight(clone, BLUE);
+            }
+            Step::CloneLinks { q, clone, cur } => {
+                highlight(cur, BLUE);
+                highlight(q, GREEN);
+                highlight(clone, BLUE);
+            }
+            Step::Done => {}
+            Step::Query { k } => {
+                if let Some(v) = self.forward[k] {
+                    highlight(v, BLUE);
+                }
+            }
+        }
+
+        if let Step::Query { k } = state.step {
+            let ql = q.len();
+            draw_label(pquery.left(1), "Q", canvas);
+            draw_string(pquery, q, |_| WHITE, canvas);
+            let forward = self.forward[k].is_some();
+            if k > 0 {
+                let colour = if forward { BLUE } else { RED };
+                draw_highlight_box(pquery, k, 1, colour, canvas);
+            }
+            draw_text(pquery.right(ql + 1), "automaton →", canvas);
+            draw_label(pquery_r.left(1), "Q", canvas);
+            draw_string(pquery_r, q, |_| WHITE, canvas);
+            if k > 0 {
+                draw_highlight_box(pquery_r.right(ql - k), k, 1, BLUE, canvas);
+            }
+            let (start, end) = self.backward[k];
+            draw_text(
+                pquery_r.right(ql + 1),
+                &format!("← BWT [{start}, {end})"),
+                canvas,
+            );
+        }
+        true
+    }
+}
@@ -0,0 +1,472 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{cmp::max, collections::BTreeMap};
+
+use crate::{
+    alg::{validate_string, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+pub const EDGE_COLOUR: Color = (220, 220, 220);
+pub const LEAF_COLOUR: Color = (150, 200, 255);
+pub const LINK_COLOUR: Color = (160, 80, 200);
+
+/// Where each node is drawn, as (column, row) in a tree laid out from left to right.
+/// The edge to a child is drawn as `len` characters after its parent, followed by the child.
+/// The first child shares the row of its parent, other children start below the previous subtree.
+/// `children(u)` gives the children of `u` in order, with the length of their edge.
+pub fn tree_layout(
+    num_nodes: usize,
+    root: usize,
+    children: impl Fn(usize) -> Vec<(usize, usize)>,
+) -> Vec<Option<(usize, usize)>> {
+    let mut pos = vec![None; num_nodes];
+    // Returns the first free row below the subtree.
+    fn place(
+        u: usize,
+        x: usize,
+        row: usize,
+        children: &dyn Fn(usize) -> Vec<(usize, usize)>,
+        pos: &mut Vec<Option<(usize, usize)>>,
+    ) -> usize {
+        pos[u] = Some((x, row));
+        let mut next = row;
+        for (v, len) in children(u) {
+            next = place(v, x + len + 1, next, children, pos);
+        }
+        max(next, row + 1)
+    }
+    place(root, 0, 0, &children, &mut pos);
+    pos
+}
+
+#[derive(Clone, Serialize)]
+struct Node {
+    start: usize,
+    /// Exclusive end of the edge label; `None` for leaves, which grow with the text.
+    end: Option<usize>,
+    link: Option<usize>,
+    children: BTreeMap<u8, usize>,
+}
+
+impl Node {
+    fn new(start: usize, end: Option<usize>) -> Self {
+        Self {
+            start,
+            end,
+            link: None,
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum Step {
+    // Add the next character; all leaves grow with it.
+    Phase,
+    // The active length covers the whole edge to `node`, which becomes the active node.
+    WalkDown { node: usize },
+    // Explicit extension: a new leaf below the active node.
+    // Nodes are numbered in order of creation, with the root as 0.
+    Leaf { leaf: usize },
+    // Explicit extension: the edge is split by `node`, with a new leaf below it.
+    Split { node: usize, leaf: usize },
+    // Implicit extension: the character is already there, which ends the phase.
+    Implicit,
+    // Move the active point from the root to the next suffix.
+    Root,
+    // Follow the suffix link from `node`, or go to the root.
+    Link { node: usize },
+    Done,
+}
+
+#[derive(Clone, Serialize)]
+pub struct State {
+    /// The phase: the tree contains the suffixes of `s[..=i]`.
+    i: usize,
+    step: Step,
+    nodes: Vec<Node>,
+    active_node: usize,
+    active_edge: usize,
+    active_length: usize,
+    remainder: usize,
+}
+
+pub struct Ukkonen {
+    s: Vec<u8>,
+    states: Vec<State>,
+    width: usize,
+    rows: usize,
+}
+
+impl Ukkonen {
+    pub fn new(s: Vec<u8>) -> Result<Self, InputError> {
+        validate_string(&s)?;
+        let n = s.len();
+        let mut nodes = vec![Node::new(0, Some(0))];
+        let mut states = vec![];
+        let (mut active_node, mut active_edge, mut active_length) = (0, 0, 0);
+        let mut remainder = 0;
+        let mut push = |i, step, nodes: &Vec<Node>, an, ae, al, rem| {
+            states.push(State {
+                i,
+                step,
+                nodes: nodes.clone(),
+                active_node: an,
+                active_edge: ae,
+                active_length: al,
+                remainder: rem,
+            })
+        };
+
+        for i in 0..n {
+            remainder += 1;
+            // The last new internal node, waiting for its suffix link.
+            let mut pending: Option<usize> = None;
+            macro_rules! push {
+                ($step:expr) => {
+                    push(
+                        i,
+                        $step,
+                        &nodes,
+                        active_node,
+                        active_edge,
+                        active_length,
+                        remainder,
+                    )
+                };
+            }
+            push!(Step::Phase);
+            while remainder > 0 {
+                if active_length == 0 {
+                    active_edge = i;
+                }
+                match nodes[active_node].children.get(&s[active_edge]).copied() {
+                    None => {
+                        let leaf = nodes.len();
+                        nodes.push(Node::new(i, None));
+                        nodes[active_node].children.insert(s[i], leaf);
+                        if let Some(p) = pending.take() {
+                            nodes[p].link = Some(active_node);
+                        }
+                        push!(Step::Leaf { leaf });
+                    }
+                    Some(next) => {
+                        let len = nodes[next].end.unwrap_or(i + 1) - nodes[next].start;
+                        if active_length >= len {
+                            active_node = next;
+                            active_edge += len;
+                            active_length -= len;
+                            push!(Step::WalkDown { node: next });
+                            continue;
+                        }
+                        if s[nodes[next].start + active_length] == s[i] {
+                            if let Some(p) = pending.take() {
+                                nodes[p].link = Some(active_node);
+                            }
+                            active_length += 1;
+                            push!(Step::Implicit);
+                            break;
+                        }
+                        let start = nodes[next].start;
+                        let node = nodes.len();
+                        nodes.push(Node::new(start, Some(start + active_length)));
+                        let leaf = nodes.len();
+                        nodes.push(Node::new(i, None));
+                        nodes[active_node].children.insert(s[active_edge], node);
+                        nodes[next].start += active_length;
+                        let c = s[nodes[next].start];
+                        nodes[node].children.insert(c, next);
+                        nodes[node].children.insert(s[i], leaf);
+                        if let Some(p) = pending.replace(node) {
+                            nodes[p].link = Some(node);
+                        }
+                        push!(Step::Split { node, leaf });
+                    }
+                }
+                remainder -= 1;
+                if active_node == 0 && active_length > 0 {
+                    active_length -= 1;
+                    active_edge = i + 1 - remainder;
+                    push!(Step::Root);
+                } else if active_node != 0 {
+                    let node = active_node;
+                    active_node = nodes[active_node].link.unwrap_or(0);
+                    push!(Step::Link { node });
+                }
+            }
+        }
+        push(
+            n - 1,
+            Step::Done,
+            &nodes,
+            active_node,
+            active_edge,
+            active_length,
+            remainder,
+        );
+
+        let mut alg = Self {
+            s,
+            states,
+            width: 0,
+            rows: 0,
+        };
+        for state in &alg.states {
+            for (x, row) in alg.layout(state).into_iter().flatten() {
+                alg.width = max(alg.width, x + 1);
+                alg.rows = max(alg.rows, row + 1);
+            }
+        }
+        Ok(alg)
+    }
+
+    /// The exclusive end of the edge into `node`.
+    fn end(&self, state: &State, node: usize) -> usize {
+        state.nodes[node].end.unwrap_or(state.i + 1)
+    }
+
+    fn layout(&self, state: &State) -> Vec<Option<(usize, usize)>> {
+        tree_layout(state.nodes.len(), 0, |u| {
+            state.nodes[u]
+                .children
+                .values()
+                .map(|&v| (v, self.end(state, v) - state.nodes[v].start))
+                .collect()
+        })
+    }
+
+    /// The depth of each node in characters.
+    fn depths(&self, state: &State) -> Vec<usize> {
+        let mut depth = vec![0; state.nodes.len()];
+        let mut stack = vec![0];
+        while let Some(u) = stack.pop() {
+            for &v in state.nodes[u].children.values() {
+                depth[v] = depth[u] + self.end(state, v) - state.nodes[v].start;
+                stack.push(v);
+            }
+        }
+        depth
+    }
+
+    fn active_point(&self, state: &State) -> String {
+        if state.active_length == 0 {
+            format!("active point: node {}", state.active_node)
+        } else {
+            format!(
+                "active point: node {}, edge {}, length {}",
+                state.active_node,
+                to_label(self.s[state.active_edge]),
+                state.active_length
+            )
+        }
+    }
+
+    fn explain(&self, state: &State) -> Explanation {
+        let i = state.i;
+        let c = to_label(self.s[i]);
+        // The suffix that is inserted by the current extension.
+        let suffix = i + 1 - state.remainder;
+        match state.step {
+            Step::Phase => Explanation::new(
+                format!("Phase {i}: add {c}"),
+                format!(
+                    "All leaves end at the current end, so they grow with {c} implicitly. \
+                     The remainder of {} suffixes still has to be inserted, \
+                     starting at the active point.",
+                    state.remainder
+                ),
+            ),
+            Step::WalkDown { node } => Explanation::new(
+                format!("Walk down to node {node}"),
+                "The active length is at least the length of the active edge, \
+                 so the active point moves to the node at its end.",
+            ),
+            Step::Leaf { .. } => Explanation::new(
+                format!("New leaf for suffix {suffix}"),
+                format!(
+                    "No edge from the active node starts with {c}, so the explicit extension \
+                     adds a leaf. A node that was split earlier in this phase gets a \
+                     suffix link to the active node."
+                ),
+            ),
+            Step::Split { node, .. } => Explanation::new(
+                format!("Split the edge for suffix {suffix}"),
+                format!(
+                    "The active edge continues with another character than {c}, so the \
+                     explicit extension splits it with node {node} and adds a leaf. \
+                     The previous node split in this phase gets a suffix link to node {node}."
+                ),
+            ),
+            Step::Implicit => Explanation::new(
+                format!("{c} is already there"),
+                format!(
+                    "The active point is followed by {c}, so this and all remaining extensions \
+                     of the phase are implicit. Increase the active length; the remainder \
+                     of {} suffixes is inserted in the next phases.",
+                    state.remainder
+                ),
+            ),
+            Step::Root => Explanation::new(
+                "Next suffix from the root",
+                "The active node is the root, so drop the first character of the active \
+                 point: decrease the active length and move the active edge one character on.",
+            ),
+            Step::Link { node } => Explanation::new(
+                format!("Follow the suffix link of node {node}"),
+                "The next suffix is one character shorter. The suffix link of the active node \
+                 leads to the same position for it, or to the root when there is no link.",
+            ),
+            Step::Done => Explanation::new(
+                "The suffix tree is complete.",
+                "Since the string ends in the unique $, every suffix ends in its own leaf.",
+            ),
+        }
+    }
+}
+
+impl Viz for Ukkonen {
+    fn canvas_size(&self) -> (usize, usize) {
+        canvas_size(
+            max(max(self.s.len() + 4, self.width + 2), 20),
+            self.rows + 8,
+        )
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![
+            ("edge", EDGE_COLOUR),
+            ("leaf", LEAF_COLOUR),
+            ("suffix link", LINK_COLOUR),
+        ]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(&self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = &self.states[state];
+        let edges: Vec<Value> = state
+            .nodes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(v, node)| {
+                json!({
+                    "node": v,
+                    "label": String::from_utf8_lossy(&self.s[node.start..self.end(state, v)]),
+                    "link": node.link,
+                    "children": node.children.values().collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        json!({
+            "i": state.i,
+            "step": state.step,
+            "s": String::from_utf8_lossy(&self.s),
+            "nodes": edges,
+            "root_children": state.nodes[0].children.values().collect::<Vec<_>>(),
+            "active_node": state.active_node,
+            "active_edge": (state.active_length > 0).then_some(state.active_edge),
+            "active_length": state.active_length,
+            "remainder": state.remainder,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = &self.states[state];
+        let title = self.explain(state).title;
+        let s = &self.s;
+        draw_background(canvas);
+
+        // Positioning
+
+        // Top left of S.
+        let ps = Pos(3, 1);
+        // The root of the tree.
+        let pt = Pos(1, 4);
+        let plabel = Pos(0, pt.1 + self.rows + 1);
+        let pinfo = plabel.down(1);
+
+        // The string, with the part that is in the tree shaded.
+        draw_string_with_labels(
+            ps,
+            s,
+            |i| if i < state.i { EDGE_COLOUR } else { WHITE },
+            canvas,
+        );
+        draw_highlight(ps.right(state.i), RED, canvas);
+        draw_text(plabel, &title, canvas);
+        draw_text(
+            pinfo,
+            &format!(
+                "{}; remainder: {}",
+                self.active_point(state),
+                state.remainder
+            ),
+            canvas,
+        );
+
+        let pos = self.layout(state);
+        let at = |u: usize| {
+            let (x, row) = pos[u].unwrap();
+            pt.right(x).down(row)
+        };
+        let depths = self.depths(state);
+        for (v, node) in state.nodes.iter().enumerate() {
+            for &w in node.children.values() {
+                // The edge label, between the two nodes.
+                let label = &s[state.nodes[w].start..self.end(state, w)];
+                let p = at(w).left(label.len());
+                draw_string(p, label, |_| EDGE_COLOUR, canvas);
+                draw_edge(at(v), p, BLACK, canvas);
+            }
+            if node.children.is_empty() && v != 0 {
+                let suffix = state.i + 1 - depths[v];
+                draw_node(at(v), &suffix.to_string(), LEAF_COLOUR, canvas);
+            } else {
+                draw_node(at(v), &v.to_string(), WHITE, canvas);
+            }
+        }
+        for (v, node) in state.nodes.iter().enumerate() {
+            if let Some(w) = node.link {
+                draw_link(at(v), at(w), LINK_COLOUR, canvas);
+            }
+        }
+
+        // The active point: the active node, and the first characters of the active edge.
+        draw_highlight(at(state.active_node), RED, canvas);
+        if state.active_length > 0 {
+            if let Some(&w) = state.nodes[state.active_node]
+                .children
+                .get(&s[state.active_edge])
+            {
+                let len = self.end(state, w) - state.nodes[w].start;
+                let p = at(w).left(len);
+                draw_highlight_box(p, state.active_length.min(len), 1, RED, canvas);
+                draw_highlight_box(
+                    ps.right(state.nodes[w].start),
+                    state.active_length.min(len),
+                    1,
+                    RED,
+                    canvas,
+                );
+            }
+        }
+        match state.step {
+            Step::Leaf { leaf } => draw_highlight(at(leaf), BLUE, canvas),
+            Step::Split { node, leaf } => {
+                draw_highlight(at(node), BLUE, canvas);
+                draw_highlight(at(leaf), BLUE, canvas);
+            }
+            Step::WalkDown { node } | Step::Link { node } => draw_highlight(at(node), BLUE, canvas),
+            _ => {}
+        }
+        true
+    }
+}
@@ -0,0 +1,422 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::max;
+
+use crate::{
+    alg::{bwt::BWT, Explanation, InputError, Viz},
+    canvas::*,
+};
+
+const ZERO_COLOUR: Color = (150, 200, 255);
+const ONE_COLOUR: Color = (255, 200, 130);
+
+/// A node of the wavelet tree, for the characters `alph[lo..hi]`.
+struct Node {
+    lo: usize,
+    hi: usize,
+    level: usize,
+    /// The column of the first character, leaving a gap between siblings.
+    x: usize,
+    /// The subsequence of L of the characters of this node.
+    seq: Vec<u8>,
+    /// For internal nodes, whether each character is in the upper half.
+    bits: Vec<bool>,
+    children: Option<(usize, usize)>,
+}
+
+impl Node {
+    fn mid(&self) -> usize {
+        (self.lo + self.hi) / 2
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum Bound {
+    S,
+    T,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum State {
+    Init,
+    // Split the nodes at this level into bitvectors.
+    Level(usize),
+    // The range of rows after `step` backward search steps.
+    Range(usize),
+    // The rank query for the given bound of the range, at this level of the tree.
+    Rank {
+        step: usize,
+        bound: Bound,
+        level: usize,
+    },
+}
+
+pub struct WaveletTree {
+    /// The BWT whose L column the tree stores, and its backward search.
+    bwt: BWT,
+    /// The last column of the sorted rotations.
+    l: Vec<u8>,
+    nodes: Vec<Node>,
+    levels: usize,
+    states: Vec<State>,
+}
+
+impl WaveletTree {
+    pub fn new(s: Vec<u8>, q: Vec<u8>) -> Result<Self, InputError> {
+        let bwt = BWT::new(s, q, 1, 1)?;
+        let l: Vec<u8> = (0..bwt.s.len()).map(|j| bwt.last(j)).collect();
+        let alph = &bwt.alph;
+
+        // Build the tree top-down: the root has all of L.
+        let mut nodes = vec![Node {
+            lo: 0,
+            hi: alph.len(),
+            level: 0,
+            x: 0,
+            seq: l.clone(),
+            bits: vec![],
+            children: None,
+        }];
+        let mut v = 0;
+        while v < nodes.len() {
+            let node = &nodes[v];
+            if node.hi - node.lo > 1 {
+                let mid = node.mid();
+                let upper = |c: &u8| alph.binary_search(c).unwrap() >= mid;
+                let bits: Vec<bool> = node.seq.iter().map(upper).collect();
+                let (ones, zeros): (Vec<u8>, Vec<u8>) = node.seq.iter().partition(|&&c| upper(&c));
+                let left = Node {
+                    lo: node.lo,
+                    hi: mid,
+                    level: node.level + 1,
+                    x: node.x,
+                    seq: zeros,
+                    bits: vec![],
+                    children: None,
+                };
+                let right = Node {
+                    lo: mid,
+                    hi: node.hi,
+                    level: node.level + 1,
+                    x: node.x + left.seq.len() + 1,
+                    seq: ones,
+                    bits: vec![],
+                    children: None,
+                };
+                let k = nodes.len();
+                nodes[v].bits = bits;
+                nodes[v].children = Some((k, k + 1));
+                nodes.push(left);
+                nodes.push(right);
+            }
+            v += 1;
+        }
+        let levels = nodes.iter().map(|v| v.level).max().unwrap() + 1;
+
+        let mut alg = Self {
+            bwt,
+            l,
+            nodes,
+            levels,
+            states: vec![],
+        };
+
+        let ql = alg.bwt.q.len();
+        let mut states = vec![State::Init];
+        states.extend((0..levels - 1).map(State::Level));
+        for step in 0..=ql {
+            states.push(State::Range(step));
+            if step < ql {
+                let c = alg.char_index(alg.bwt.q[ql - 1 - step]);
+                let depth = alg.rank_path(c, 0).len();
+                for bound in [Bound::S, Bound::T] {
+                    states.extend((0..depth).map(|level| State::Rank { step, bound, level }));
+                }
+            }
+        }
+        alg.states = states;
+        Ok(alg)
+    }
+
+    fn char_index(&self, c: u8) -> usize {
+        self.bwt.alph.binary_search(&c).unwrap()
+    }
+
+    /// The nodes from the root to the leaf of `alph[ci]`, with the position `j` in each.
+    fn rank_path(&self, ci: usize, mut j: usize) -> Vec<(usize, usize)> {
+        let mut path = vec![];
+        let mut v = 0;
+        loop {
+            path.push((v, j));
+            let node = &self.nodes[v];
+            let Some((left, right)) = node.children else {
+                return path;
+            };
+            let bit = ci >= node.mid();
+            j = node.bits[..j].iter().filter(|&&b| b == bit).count();
+            v = if bit { right } else { left };
+        }
+    }
+
+    fn half(&self, lo: usize, hi: usize) -> String {
+        String::from_utf8_lossy(&self.bwt.alph[lo..hi]).into_owned()
+    }
+
+    /// The characters of a node, split into the two halves for internal nodes.
+    fn node_label(&self, node: &Node) -> String {
+        if node.children.is_some() {
+            format!(
+                "{}|{}",
+                self.half(node.lo, node.mid()),
+                self.half(node.mid(), node.hi)
+            )
+        } else {
+            self.half(node.lo, node.hi)
+        }
+    }
+
+    /// The query character and the position of the rank query.
+    fn rank_query(&self, step: usize, bound: Bound) -> (usize, usize) {
+        let ql = self.bwt.q.len();
+        let ci = self.char_index(self.bwt.q[ql - 1 - step]);
+        let (s, t) = self.bwt.j_begin_end[step];
+        (ci, if bound == Bound::S { s } else { t })
+    }
+
+    fn explain(&self, state: State) -> Explanation {
+        let ql = self.bwt.q.len();
+        match state {
+            State::Init => Explanation::new(
+                "The L column of the BWT.",
+                "Backward search needs Occ[c][j], the number of times c occurs in L[..j]. \
+                 Instead of a table of σ(n+1) counts, a wavelet tree answers this rank query in \
+                 O(log σ) steps using n log σ bits.",
+            ),
+            State::Level(level) => Explanation::new(
+                format!("Level {level}: split the alphabets"),
+                "Each node splits its characters in a lower half (bit 0) and an upper half \
+                 (bit 1), and stores one bit per character. The characters go to the left and \
+                 right child in their original order.",
+            ),
+            State::Range(step) => {
+                let (s, t) = self.bwt.j_begin_end[step];
+                if step < ql {
+                    let c = to_label(self.bwt.q[ql - 1 - step]);
+                    Explanation::new(
+                        format!("[s, t) = [{s}, {t}), next {c}"),
+                        format!(
+                            "The rows starting with the last {step} characters of Q are \
+                             [{s}, {t}). Prepending {c} gives C[{c}] + Occ[{c}][{s}] and \
+                             C[{c}] + Occ[{c}][{t}], where both Occ lookups are rank queries \
+                             in the wavelet tree."
+                        ),
+                    )
+                } else {
+                    Explanation::new(
+                        "Query done",
+                        format!("Q occurs {} times, in rows [{s}, {t}).", t - s),
+                    )
+                }
+            }
+            State::Rank { step, bound, level } => {
+                let (ci, j) = self.rank_query(step, bound);
+                let c = to_label(self.bwt.alph[ci]);
+                let path = self.rank_path(ci, j);
+                let (v, jv) = path[level];
+                let node = &self.nodes[v];
+                if node.children.is_none() {
+                    let name = if bound == Bound::S { "s" } else { "t" };
+                    let occ = self.bwt.occ[ci][j];
+                    debug_assert_eq!(jv, occ as usize);
+                    let new = self.bwt.j_begin_end[step + 1];
+                    let new = if bound == Bound::S { new.0 } else { new.1 };
+                    return Explanation::new(
+                        format!("Occ[{c}][{j}] = {occ}"),
+                        format!(
+                            "The leaf of {c} is reached at position {jv}, so L[..{j}] contains \
+                             {occ} times {c}. The new {name} = C[{c}] + {occ} = {new}."
+                        ),
+                    );
+                }
+                let bit = ci >= node.mid();
+                let next = path[level + 1].1;
+                Explanation::new(
+                    format!(
+                        "rank({c}, {j}): level {level}, rank{}({jv}) = {next}",
+                        bit as u8
+                    ),
+                    format!(
+                        "In the node for {}, {c} has bit {}. Of the first {jv} bits, {next} are \
+                         {}, so continue in the {} child at position {next}.",
+                        self.node_label(node),
+                        bit as u8,
+                        bit as u8,
+                        if bit { "right" } else { "left" },
+                    ),
+                )
+            }
+        }
+    }
+}
+
+impl Viz for WaveletTree {
+    fn canvas_size(&self) -> (usize, usize) {
+        let n = self.bwt.s.len();
+        canvas_size(max(n + self.levels + 5, 24), 4 * self.levels + 8)
+    }
+
+    fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn palette(&self) -> Vec<(&'static str, Color)> {
+        vec![("bit 0", ZERO_COLOUR), ("bit 1", ONE_COLOUR)]
+    }
+
+    fn explanation(&self, state: usize) -> Explanation {
+        self.explain(self.states[state])
+    }
+
+    fn trace(&self, state: usize) -> Value {
+        let state = self.states[state];
+        let built = match state {
+            State::Init => 0,
+            State::Level(level) => level + 1,
+            _ => self.levels,
+        };
+        let nodes: Vec<Value> = self
+            .nodes
+            .iter()
+            .filter(|v| v.level <= built)
+            .map(|v| {
+                let bits = (v.level < built && v.children.is_some()).then(|| {
+                    v.bits
+                        .iter()
+                        .map(|&b| if b { '1' } else { '0' })
+                        .collect::<String>()
+                });
+                json!({
+                    "level": v.level,
+                    "chars": self.half(v.lo, v.hi),
+                    "seq": String::from_utf8_lossy(&v.seq),
+                    "bits": bits,
+                })
+            })
+            .collect();
+        let rank = match state {
+            State::Rank { step, bound, level } => {
+                let (ci, j) = self.rank_query(step, bound);
+                let path = self.rank_path(ci, j);
+                json!({
+                    "char": to_label(self.bwt.alph[ci]),
+                    "j": j,
+                    "positions": path[..=level].iter().map(|&(_, j)| j).collect::<Vec<_>>(),
+                    "result": (level + 1 == path.len()).then_some(path[level].1),
+                })
+            }
+            _ => Value::Null,
+        };
+        let step = match state {
+            State::Range(step) | State::Rank { step, .. } => Some(step),
+            _ => None,
+        };
+        json!({
+            "state": state,
+            "s": String::from_utf8_lossy(&self.bwt.s),
+            "l": String::from_utf8_lossy(&self.l),
+            "nodes": nodes,
+            "q": String::from_utf8_lossy(&self.bwt.q),
+            "range": step.map(|step| self.bwt.j_begin_end[step]),
+            "rank": rank,
+        })
+    }
+
+    fn draw(&self, state: usize, canvas: &mut CanvasBox) -> bool {
+        let state = self.states[state];
+        let title = self.explain(state).title;
+        let q = &self.bwt.q;
+        let ql = q.len();
+        let n = self.l.len();
+        draw_background(canvas);
+
+        // Positioning
+
+        // Top left of L.
+        let pl = Pos(3, 1);
+        // Top left of the root, with the rows of each level 4 apart.
+        let ptree = Pos(3, 4);
+        let at = |node: &Node| ptree.right(node.x).down(4 * node.level);
+        let pq = Pos(3, 4 * self.levels + 3);
+        let prange = pq.down(1);
+        let plabel = Pos(0, pq.1 + 2);
+
+        draw_label(pl.left(1), "L", canvas);
+        for j in 0..=n {
+            draw_label(pl.up(1).right(j), &j.to_string(), canvas);
+        }
+        let built = match state {
+            State::Init => 0,
+            State::Level(level) => level + 1,
+            _ => self.levels,
+        };
+        let colour = |node: &Node, k: usize| match node.bits.get(k) {
+            Some(true) if node.level < built => ONE_COLOUR,
+            Some(false) if node.level < built => ZERO_COLOUR,
+            _ => WHITE,
+        };
+        draw_string(pl, &self.l, |j| colour(&self.nodes[0], j), canvas);
+
+        for level in 0..=built.min(self.levels - 1) {
+            draw_label(ptree.left(2).down(4 * level), &format!("{level}"), canvas);
+        }
+        for node in self.nodes.iter().filter(|v| v.level <= built) {
+            let p = at(node);
+            draw_text(p.up(1), &self.node_label(node), canvas);
+            draw_string(p, &node.seq, |k| colour(node, k), canvas);
+            if node.level < built && node.children.is_some() {
+                for (k, &b) in node.bits.iter().enumerate() {
+                    draw_label(p.down(1).right(k), if b { "1" } else { "0" }, canvas);
+                }
+            }
+        }
+        draw_text(plabel, &title, canvas);
+
+        // The backward search.
+        let step = match state {
+            State::Range(step) | State::Rank { step, .. } => step,
+            _ => return true,
+        };
+        let (s, t) = self.bwt.j_begin_end[step];
+        draw_label(pq.left(1), "Q", canvas);
+        draw_string(pq, q, |k| if k >= ql - step { CYAN } else { WHITE }, canvas);
+        if step < ql {
+            draw_highlight(pq.right(ql - 1 - step), BLUE, canvas);
+        }
+        draw_text(prange.left(3), &format!("[s, t) = [{s}, {t})"), canvas);
+        if s < t {
+            draw_highlight_box(pl.right(s), t - s, 1, BLACK, canvas);
+        }
+
+        if let State::Rank { step, bound, level } = state {
+            let (ci, j) = self.rank_query(step, bound);
+            let path = self.rank_path(ci, j);
+            draw_highlight_box(pl, j, 1, BLUE, canvas);
+            for (k, &c) in self.l[..j].iter().enumerate() {
+                if c == self.bwt.alph[ci] {
+                    draw_highlight(pl.right(k), RED, canvas);
+                }
+            }
+            for &(v, jv) in &path[..level] {
+                draw_highlight_box(at(&self.nodes[v]).down(1), jv, 1, BLACK, canvas);
+            }
+            let (v, jv) = path[level];
+            let p = at(&self.nodes[v]);
+            if self.nodes[v].children.is_some() {
+                draw_highlight_box(p.down(1), jv, 1, BLUE, canvas);
+            } else {
+                draw_highlight_box(p, jv, 1, RED, canvas);
+            }
+        }
+        true
+    }
+}
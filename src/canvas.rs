@@ -2,9 +2,21 @@
 #[cfg(feature = "wasm")]
 pub mod html;
 
-#[cfg(feature = "bin")]
+#[cfg(feature = "sdl")]
 pub mod sdl;
 
+#[cfg(feature = "bin")]
+pub mod animation;
+#[cfg(feature = "bin")]
+pub mod raster;
+#[cfg(feature = "bin")]
+pub mod terminal;
+
+pub mod recording;
+pub mod svg;
+pub mod tikz;
+
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, Sub};
 
 pub type Color = (u8, u8, u8);
@@ -15,13 +27,23 @@ pub const GREEN: Color = (0, 255, 0);
 pub const BLUE: Color = (0, 0, 255);
 pub const CYAN: Color = (0, 255, 255);
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Names for the colors above, for backends that refer to colors by name.
+pub const PALETTE: [(&str, Color); 6] = [
+    ("black", BLACK),
+    ("white", WHITE),
+    ("red", RED),
+    ("green", GREEN),
+    ("blue", BLUE),
+    ("cyan", CYAN),
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum HAlign {
     Left,
     Center,
     Right,
 }
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum VAlign {
     Top,
     Center,
@@ -35,8 +57,33 @@ pub trait Canvas {
 
     fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str);
 
-    fn save(&mut self);
+    fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), color: Color);
+    /// A quadratic Bézier curve, drawn as line segments unless the backend has curves.
+    fn draw_curve(&mut self, from: (i32, i32), control: (i32, i32), to: (i32, i32), color: Color) {
+        const SEGMENTS: i32 = 16;
+        let point = |k: i32| {
+            let t = k as f32 / SEGMENTS as f32;
+            let bezier = |a: i32, b: i32, c: i32| {
+                ((1. - t) * (1. - t) * a as f32 + 2. * (1. - t) * t * b as f32 + t * t * c as f32)
+                    .round() as i32
+            };
+            (
+                bezier(from.0, control.0, to.0),
+                bezier(from.1, control.1, to.1),
+            )
+        };
+        for k in 0..SEGMENTS {
+            self.draw_line(point(k), point(k + 1), color);
+        }
+    }
+
+    /// Write the current frame, for backends that write files.
+    fn save(&mut self) -> std::io::Result<()>;
     fn present(&mut self) {}
+    /// Called once after the last frame has been saved.
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 pub type CanvasBox = Box<dyn Canvas>;
@@ -125,7 +172,12 @@ pub fn draw_text(Pos(x, y): Pos, label: &str, canvas: &mut CanvasBox) {
     );
 }
 
-pub fn draw_char_box(Pos(x, y): Pos, c: u8, color: Color, canvas: &mut CanvasBox) {
+pub fn draw_char_box(p: Pos, c: u8, color: Color, canvas: &mut CanvasBox) {
+    draw_text_box(p, &to_label(c), color, canvas);
+}
+
+/// A cell like `draw_char_box`, containing arbitrary text such as a number.
+pub fn draw_text_box(Pos(x, y): Pos, text: &str, color: Color, canvas: &mut CanvasBox) {
     let x = x as i32 * CS as i32;
     let y = y as i32 * CS as i32;
     canvas.fill_rect(x, y, CS, CS, color);
@@ -136,7 +188,7 @@ pub fn draw_char_box(Pos(x, y): Pos, c: u8, color: Color, canvas: &mut CanvasBox
         y + CS as i32 / 2,
         HAlign::Center,
         VAlign::Center,
-        &to_label(c),
+        text,
     );
 }
 
@@ -211,3 +263,83 @@ pub fn draw_string_with_labels(
     draw_label(Pos(x - 1, y), "S", canvas);
     draw_string(Pos(x, y), s, color, canvas);
 }
+
+// Trees: nodes are cells, connected by edges and links between cells.
+
+fn center(Pos(x, y): Pos) -> (i32, i32) {
+    (
+        x as i32 * CS as i32 + CS as i32 / 2,
+        y as i32 * CS as i32 + CS as i32 / 2,
+    )
+}
+
+/// A node is a box like a character.
+pub fn draw_node(p: Pos, label: &str, color: Color, canvas: &mut CanvasBox) {
+    draw_text_box(p, label, color, canvas);
+}
+
+/// An edge from the bottom of `from` that bends right into the left side of `to`,
+/// or a straight line when they are in the same row.
+pub fn draw_edge(from: Pos, to: Pos, color: Color, canvas: &mut CanvasBox) {
+    let (fx, fy) = center(from);
+    let (tx, ty) = center(to);
+    let half = CS as i32 / 2;
+    if from.1 == to.1 {
+        canvas.draw_line((fx + half, fy), (tx - half, ty), color);
+    } else {
+        canvas.draw_curve((fx, fy + half), (fx, ty), (tx - half, ty), color);
+    }
+}
+
+/// The control point of a link, to the right of the line between the centers.
+fn link_control((fx, fy): (i32, i32), (tx, ty): (i32, i32)) -> (i32, i32) {
+    let (dx, dy) = (tx - fx, ty - fy);
+    ((fx + tx) / 2 - dy / 3, (fy + ty) / 2 + dx / 3)
+}
+
+/// A curved arrow from `from` to `to` with the given control point.
+fn draw_arrow(
+    from: (i32, i32),
+    control: (i32, i32),
+    to: (i32, i32),
+    color: Color,
+    canvas: &mut CanvasBox,
+) {
+    let (tx, ty) = to;
+    canvas.draw_curve(from, control, to, color);
+    // The arrow head, along the direction from the control point to the end.
+    let (ax, ay) = ((tx - control.0) as f32, (ty - control.1) as f32);
+    let len = (ax * ax + ay * ay).sqrt().max(1.);
+    let (ux, uy) = (ax / len * 8., ay / len * 8.);
+    for (sx, sy) in [(-uy, ux), (uy, -ux)] {
+        let p = (
+            (tx as f32 - ux + sx / 2.) as i32,
+            (ty as f32 - uy + sy / 2.) as i32,
+        );
+        canvas.draw_line(p, (tx, ty), color);
+    }
+}
+
+/// A curved arrow between the centers of two cells, bending to its right.
+pub fn draw_link(from: Pos, to: Pos, color: Color, canvas: &mut CanvasBox) {
+    let (f, t) = (center(from), center(to));
+    draw_arrow(f, link_control(f, t), t, color, canvas);
+}
+
+/// A curved arrow between the borders of two cells, with a label halfway.
+pub fn draw_labelled_link(from: Pos, to: Pos, label: &str, color: Color, canvas: &mut CanvasBox) {
+    let (f, t) = (center(from), center(to));
+    let c = link_control(f, t);
+    // Move the ends from the centers towards the control point, out of the cells.
+    let border = |(x, y): (i32, i32)| {
+        let (dx, dy) = ((c.0 - x) as f32, (c.1 - y) as f32);
+        let len = (dx * dx + dy * dy).sqrt().max(1.);
+        let r = (CS / 2 + 2) as f32;
+        (x + (dx / len * r) as i32, y + (dy / len * r) as i32)
+    };
+    draw_arrow(border(f), c, border(t), color, canvas);
+    let (x, y) = ((f.0 + 2 * c.0 + t.0) / 4, (f.1 + 2 * c.1 + t.1) / 4);
+    let half = CS as i32 / 4;
+    canvas.fill_rect(x - half, y - half, CS / 2, CS / 2, BACKGROUND);
+    canvas.write_text(x, y, HAlign::Center, VAlign::Center, label);
+}
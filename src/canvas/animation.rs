@@ -0,0 +1,136 @@
+use std::{fs::File, io, io::BufWriter, path::PathBuf, time::Duration};
+
+use crate::canvas::{raster::RasterCanvas, Canvas, Color, HAlign, VAlign};
+
+/// The first and last frame are shown this many times as long, so that the input and the result
+/// can be read before the animation loops.
+const HOLD: u32 = 3;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+/// Collects every saved frame and writes them as a single animated image on `finish()`.
+pub struct AnimationCanvas {
+    raster: RasterCanvas,
+    w: u32,
+    h: u32,
+    path: PathBuf,
+    format: AnimationFormat,
+    delay: Duration,
+    frames: Vec<(Vec<u8>, Duration)>,
+}
+
+/// GIF sizes are 16 bit, so larger canvases are rejected.
+fn gif_size(w: u32, h: u32) -> io::Result<(u16, u16)> {
+    match (u16::try_from(w), u16::try_from(h)) {
+        (Ok(w), Ok(h)) => Ok((w, h)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the animation is {w}x{h} pixels, but a GIF can be at most {}x{}",
+                u16::MAX,
+                u16::MAX
+            ),
+        )),
+    }
+}
+
+impl AnimationCanvas {
+    pub fn new(
+        w: u32,
+        h: u32,
+        path: PathBuf,
+        format: AnimationFormat,
+        delay: Duration,
+    ) -> io::Result<Self> {
+        if format == AnimationFormat::Gif {
+            gif_size(w, h)?;
+        }
+        Ok(Self {
+            raster: RasterCanvas::new(w, h, None),
+            w,
+            h,
+            path,
+            format,
+            delay,
+            frames: vec![],
+        })
+    }
+
+    fn write_gif(&self, file: BufWriter<File>) -> Result<(), gif::EncodingError> {
+        let (w, h) = gif_size(self.w, self.h)?;
+        let mut encoder = gif::Encoder::new(file, w, h, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for (rgb, delay) in &self.frames {
+            let mut frame = gif::Frame::from_rgb_speed(w, h, rgb, 10);
+            // GIF delays are in units of 10ms, and viewers replace a delay of 0 by 100ms.
+            frame.delay = (delay.as_millis() / 10).clamp(1, u16::MAX as u128) as u16;
+            encoder.write_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn write_apng(&self, file: BufWriter<File>) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(file, self.w, self.h);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        for (rgb, delay) in &self.frames {
+            writer.set_frame_delay(delay.as_millis().clamp(1, u16::MAX as u128) as u16, 1000)?;
+            writer.write_image_data(rgb)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl Canvas for AnimationCanvas {
+    fn fill_background(&mut self, color: Color) {
+        self.raster.fill_background(color);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        self.raster.fill_rect(x, y, w, h, color);
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        self.raster.draw_rect(x, y, w, h, color);
+    }
+
+    fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str) {
+        self.raster.write_text(x, y, ha, va, text);
+    }
+
+    fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), color: Color) {
+        self.raster.draw_line(from, to, color);
+    }
+
+    fn save(&mut self) -> io::Result<()> {
+        let delay = if self.frames.is_empty() {
+            self.delay * HOLD
+        } else {
+            self.delay
+        };
+        self.frames.push((self.raster.rgb(), delay));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let Some((_, last)) = self.frames.last_mut() else {
+            return Ok(());
+        };
+        *last = self.delay * HOLD;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = BufWriter::new(File::create(&self.path)?);
+        match self.format {
+            AnimationFormat::Gif => self.write_gif(file).map_err(io::Error::other),
+            AnimationFormat::Apng => self.write_apng(file),
+        }
+    }
+}
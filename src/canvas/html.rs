@@ -1,5 +1,18 @@
-use crate::alg::Viz;
-use crate::alg::{bibwt::BiBWT, bwt::BWT, suffix_array::SA};
+use crate::alg::{
+    approx_search::ApproxSearch,
+    bibwt::{parse_order, parse_scheme, BiBWT},
+    bwt::BWT,
+    dc3::DC3,
+    lcp::LCP,
+    lcp_tree::LcpTree,
+    prefix_doubling::PrefixDoubling,
+    sais::SAIS,
+    suffix_array::SA,
+    suffix_automaton::SuffixAutomaton,
+    ukkonen::Ukkonen,
+    wavelet_tree::WaveletTree,
+};
+use crate::alg::{InputError, Viz};
 use crate::canvas::Canvas;
 use crate::canvas::CanvasBox;
 use crate::canvas::Color;
@@ -11,6 +24,7 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use web_sys::CanvasRenderingContext2d;
 use web_sys::HtmlCanvasElement;
+use web_sys::HtmlElement;
 use web_sys::HtmlInputElement;
 use web_sys::HtmlSelectElement;
 
@@ -85,12 +99,37 @@ impl Canvas for HtmlCanvas {
         self.context.fill_text(text, x as f64, y as f64).unwrap();
     }
 
+    fn draw_line(&mut self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), color: Color) {
+        self.context.begin_path();
+        self.context.set_stroke_style(&jscol(color));
+        self.context.move_to(x1 as f64, y1 as f64);
+        self.context.line_to(x2 as f64, y2 as f64);
+        self.context.stroke();
+    }
+
+    fn draw_curve(
+        &mut self,
+        (x1, y1): (i32, i32),
+        (cx, cy): (i32, i32),
+        (x2, y2): (i32, i32),
+        color: Color,
+    ) {
+        self.context.begin_path();
+        self.context.set_stroke_style(&jscol(color));
+        self.context.move_to(x1 as f64, y1 as f64);
+        self.context
+            .quadratic_curve_to(cx as f64, cy as f64, x2 as f64, y2 as f64);
+        self.context.stroke();
+    }
+
     // no-op
-    fn save(&mut self) {}
+    fn save(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 thread_local! {
-    static ALG: RefCell<Box<dyn Viz>> = RefCell::new(Box::new(SA::new("GTCCCGATGTCATGTCAGGA$".as_bytes().to_vec())));
+    static ALG: RefCell<Box<dyn Viz>> = RefCell::new(Box::new(SA::new("GTCCCGATGTCATGTCAGGA$".as_bytes().to_vec(), false).unwrap()));
 }
 static mut INTERACTION: Interaction = Interaction::default();
 
@@ -105,11 +144,60 @@ pub fn reset() {
     if query.is_empty() {
         query = "GTCC".as_bytes().to_vec()
     };
+    let full = get::<HtmlInputElement>("full").checked();
+    let sample_rate = get::<HtmlInputElement>("sample-rate")
+        .value()
+        .parse()
+        .unwrap_or(4);
+    let checkpoint = get::<HtmlInputElement>("checkpoint")
+        .value()
+        .parse()
+        .unwrap_or(1);
+    let mismatches = get::<HtmlInputElement>("mismatches")
+        .value()
+        .parse()
+        .unwrap_or(1);
+    let scheme = get::<HtmlInputElement>("scheme").value();
+    let start = get::<HtmlInputElement>("start").value().parse().ok();
+    let order = get::<HtmlInputElement>("order").value();
     let new_alg = match alg_name.as_str() {
-        "suffix-array" => Box::new(SA::new(string)) as Box<dyn Viz>,
-        "bwt" => Box::new(BWT::new(string, query)) as Box<dyn Viz>,
-        "bibwt" => Box::new(BiBWT::new(string, query)) as Box<dyn Viz>,
-        _ => panic!(),
+        "suffix-array" => SA::new(string, full).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "sais" => SAIS::new(string).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "prefix-doubling" => PrefixDoubling::new(string).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "dc3" => DC3::new(string).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "lcp" => LCP::new(string).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "ukkonen" => Ukkonen::new(string).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "lcp-tree" => LcpTree::new(string).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "suffix-automaton" => {
+            SuffixAutomaton::new(string, query).map(|alg| Box::new(alg) as Box<dyn Viz>)
+        }
+        "bwt" => BWT::new(string, query, sample_rate, checkpoint)
+            .map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "approx-search" => {
+            ApproxSearch::new(string, query, mismatches).map(|alg| Box::new(alg) as Box<dyn Viz>)
+        }
+        "bibwt" => parse_order(order.trim())
+            .and_then(|order| {
+                let scheme = Some(scheme.trim())
+                    .filter(|scheme| !scheme.is_empty())
+                    .map_or(Ok(vec![]), parse_scheme)?;
+                BiBWT::new(string, query, start, order, scheme)
+            })
+            .map(|alg| Box::new(alg) as Box<dyn Viz>),
+        "wavelet-tree" => WaveletTree::new(string, query).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        _ => Err(InputError::UnknownAlgorithm(alg_name)),
+    };
+    // Keep showing the previous visualization when the input is invalid.
+    let error = get::<HtmlElement>("error");
+    let new_alg = match new_alg {
+        Ok(new_alg) => {
+            error.set_text_content(None);
+            new_alg
+        }
+        Err(err) => {
+            error.set_text_content(Some(&err.to_string()));
+            return;
+        }
     };
     unsafe {
         INTERACTION.reset(new_alg.num_states());
@@ -123,6 +211,13 @@ pub fn reset() {
     draw();
 }
 
+/// Put the explanation of the current state in the accessible text panel next to the canvas.
+fn show_explanation(state: usize) {
+    let explanation = ALG.with(|alg| alg.borrow().explanation(state));
+    get::<HtmlElement>("explanation-title").set_text_content(Some(&explanation.title));
+    get::<HtmlElement>("explanation-description").set_text_content(Some(&explanation.description));
+}
+
 #[wasm_bindgen]
 pub fn draw() {
     let element = get::<HtmlCanvasElement>("canvas");
@@ -136,6 +231,7 @@ pub fn draw() {
     unsafe {
         loop {
             if ALG.with(|alg| alg.borrow_mut().draw(INTERACTION.get(), canvas)) {
+                show_explanation(INTERACTION.get());
                 break;
             }
             INTERACTION.step();
@@ -0,0 +1,161 @@
+use std::{fs::File, io, io::BufWriter, path::PathBuf};
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+use crate::canvas::{Canvas, Color, HAlign, VAlign, BLACK};
+
+lazy_static! {
+    // Embedded so that rendering works without any system fonts installed.
+    static ref FONT: FontRef<'static> = FontRef::try_from_slice(notosans::REGULAR_TTF).unwrap();
+}
+
+const FONT_SIZE: f32 = 24.;
+
+/// Software rasterizer that writes each frame as `dir/<frame>.png` on `save()`
+/// when a directory is given.
+/// Does not need SDL, a display, or system fonts.
+pub struct RasterCanvas {
+    w: u32,
+    h: u32,
+    pixels: Vec<Color>,
+    dir: Option<PathBuf>,
+    frame: usize,
+}
+
+impl RasterCanvas {
+    pub fn new(w: u32, h: u32, dir: Option<PathBuf>) -> Self {
+        Self {
+            w,
+            h,
+            pixels: vec![BLACK; (w * h) as usize],
+            dir,
+            frame: 0,
+        }
+    }
+
+    /// The current frame as packed 8-bit RGB.
+    pub fn rgb(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect()
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if 0 <= x && x < self.w as i32 && 0 <= y && y < self.h as i32 {
+            self.pixels[(y as u32 * self.w + x as u32) as usize] = color;
+        }
+    }
+
+    /// Mix `color` into the pixel with the given coverage in [0, 1].
+    fn blend_pixel(&mut self, x: i32, y: i32, color: Color, coverage: f32) {
+        if 0 <= x && x < self.w as i32 && 0 <= y && y < self.h as i32 {
+            let p = &mut self.pixels[(y as u32 * self.w + x as u32) as usize];
+            let mix =
+                |a: u8, b: u8| (a as f32 * (1. - coverage) + b as f32 * coverage).round() as u8;
+            *p = (mix(p.0, color.0), mix(p.1, color.1), mix(p.2, color.2));
+        }
+    }
+}
+
+impl Canvas for RasterCanvas {
+    fn fill_background(&mut self, color: Color) {
+        self.pixels.fill(color);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        for py in y..y + h as i32 {
+            for px in x..x + w as i32 {
+                self.set_pixel(px, py, color);
+            }
+        }
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        let (x2, y2) = (x + w as i32 - 1, y + h as i32 - 1);
+        for px in x..=x2 {
+            self.set_pixel(px, y, color);
+            self.set_pixel(px, y2, color);
+        }
+        for py in y..=y2 {
+            self.set_pixel(x, py, color);
+            self.set_pixel(x2, py, color);
+        }
+    }
+
+    fn draw_line(&mut self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), color: Color) {
+        let steps = (x2 - x1).abs().max((y2 - y1).abs()).max(1);
+        for k in 0..=steps {
+            let t = k as f32 / steps as f32;
+            let x = x1 as f32 + t * (x2 - x1) as f32;
+            let y = y1 as f32 + t * (y2 - y1) as f32;
+            self.set_pixel(x.round() as i32, y.round() as i32, color);
+        }
+    }
+
+    fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str) {
+        let font = FONT.as_scaled(PxScale::from(FONT_SIZE));
+
+        // Lay out the glyphs on a single line starting at the origin.
+        let mut glyphs = vec![];
+        let mut caret = 0.;
+        let mut last = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(last) = last {
+                caret += font.kern(last, id);
+            }
+            glyphs.push(id.with_scale_and_position(font.scale, point(caret, font.ascent())));
+            caret += font.h_advance(id);
+            last = Some(id);
+        }
+
+        let w = caret.ceil() as i32;
+        let h = font.height().ceil() as i32;
+        let x = match ha {
+            HAlign::Left => x,
+            HAlign::Center => x - w / 2,
+            HAlign::Right => x - w,
+        };
+        let y = match va {
+            VAlign::Top => y,
+            VAlign::Center => y - h / 2,
+            VAlign::Bottom => y - h,
+        };
+
+        for glyph in glyphs {
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                self.blend_pixel(
+                    x + bounds.min.x as i32 + gx as i32,
+                    y + bounds.min.y as i32 + gy as i32,
+                    BLACK,
+                    coverage,
+                )
+            });
+        }
+    }
+
+    fn save(&mut self) -> io::Result<()> {
+        let Some(mut path) = self.dir.clone() else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(&path)?;
+        // NOTE: We can not use zero-padded ints since ffmpeg can't handle it.
+        path.push(format!("{}", self.frame));
+        path.set_extension("png");
+
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), self.w, self.h);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.rgb())?;
+        self.frame += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::{cell::RefCell, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alg::Viz,
+    canvas::{Canvas, CanvasBox, Color, HAlign, VAlign},
+};
+
+/// A single draw call.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum Op {
+    FillBackground(Color),
+    FillRect(i32, i32, u32, u32, Color),
+    DrawRect(i32, i32, u32, u32, Color),
+    WriteText(i32, i32, HAlign, VAlign, String),
+    DrawLine((i32, i32), (i32, i32), Color),
+    DrawCurve((i32, i32), (i32, i32), (i32, i32), Color),
+}
+
+/// The draw calls of a single shown state.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub state: usize,
+    pub ops: Vec<Op>,
+}
+
+/// Records draw calls into a display list instead of rendering them.
+pub struct RecordingCanvas {
+    ops: Rc<RefCell<Vec<Op>>>,
+}
+
+impl RecordingCanvas {
+    /// The canvas, and a handle to the display list it records into.
+    pub fn new() -> (Self, Rc<RefCell<Vec<Op>>>) {
+        let ops = Rc::new(RefCell::new(vec![]));
+        (Self { ops: ops.clone() }, ops)
+    }
+}
+
+impl Canvas for RecordingCanvas {
+    fn fill_background(&mut self, color: Color) {
+        self.ops.borrow_mut().push(Op::FillBackground(color));
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        self.ops.borrow_mut().push(Op::FillRect(x, y, w, h, color));
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        self.ops.borrow_mut().push(Op::DrawRect(x, y, w, h, color));
+    }
+
+    fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str) {
+        self.ops
+            .borrow_mut()
+            .push(Op::WriteText(x, y, ha, va, text.to_string()));
+    }
+
+    fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), color: Color) {
+        self.ops.borrow_mut().push(Op::DrawLine(from, to, color));
+    }
+
+    fn draw_curve(&mut self, from: (i32, i32), control: (i32, i32), to: (i32, i32), color: Color) {
+        self.ops
+            .borrow_mut()
+            .push(Op::DrawCurve(from, control, to, color));
+    }
+
+    // no-op
+    fn save(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The display list of every state that is shown.
+pub fn record(alg: &dyn Viz) -> Vec<Frame> {
+    let (canvas, ops) = RecordingCanvas::new();
+    let mut canvas = Box::new(canvas) as CanvasBox;
+    (0..alg.num_states())
+        .filter_map(|state| {
+            let shown = alg.draw(state, &mut canvas);
+            let ops = ops.take();
+            shown.then_some(Frame { state, ops })
+        })
+        .collect()
+}
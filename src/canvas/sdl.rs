@@ -1,15 +1,14 @@
-use std::{sync::atomic::AtomicUsize, time::Duration};
+use std::time::Duration;
 
 use crate::{
     canvas::{Canvas, Color, HAlign, VAlign, BLACK},
-    cli::ARGS,
     interaction::KeyboardAction,
 };
 
 use sdl2::{
     event::Event,
     keyboard::Keycode,
-    rect::Rect,
+    rect::{Point, Rect},
     ttf::{Font, Sdl2TtfContext},
     video::Window,
     Sdl,
@@ -88,31 +87,15 @@ impl Canvas for SdlCanvas {
         .unwrap();
     }
 
-    fn save(&mut self) {
-        if let Some(mut path) = ARGS.save.clone() {
-            static FRAME: AtomicUsize = AtomicUsize::new(0);
-
-            let pixel_format = self.default_pixel_format();
-            let mut pixels = self.read_pixels(self.viewport(), pixel_format).unwrap();
-            let (width, height) = self.output_size().unwrap();
-            let pitch = pixel_format.byte_size_of_pixels(width as usize);
-            let surf = sdl2::surface::Surface::from_data(
-                pixels.as_mut_slice(),
-                width,
-                height,
-                pitch as u32,
-                pixel_format,
-            )
+    fn draw_line(&mut self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), color: Color) {
+        self.set_draw_color(color);
+        self.draw_line(Point::new(x1, y1), Point::new(x2, y2))
             .unwrap();
+    }
 
-            std::fs::create_dir_all(&path).unwrap();
-            let frame = FRAME.load(std::sync::atomic::Ordering::Acquire);
-            // NOTE: We can not use zero-padded ints since ffmpeg can't handle it.
-            path.push(format!("{frame}"));
-            path.set_extension("bmp");
-            surf.save_bmp(path).unwrap();
-            FRAME.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
-        }
+    // no-op; frames are saved headless via `--save`.
+    fn save(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 
     fn present(&mut self) {
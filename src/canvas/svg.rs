@@ -0,0 +1,128 @@
+use std::{fmt::Write, path::PathBuf};
+
+use crate::canvas::{Canvas, Color, HAlign, VAlign, BLACK};
+
+/// Renders each frame as an SVG document and writes it to `dir/<frame>.svg` on `save()`.
+pub struct SvgCanvas {
+    w: u32,
+    h: u32,
+    dir: PathBuf,
+    frame: usize,
+    elements: String,
+}
+
+impl SvgCanvas {
+    pub fn new(w: u32, h: u32, dir: PathBuf) -> Self {
+        Self {
+            w,
+            h,
+            dir,
+            frame: 0,
+            elements: String::new(),
+        }
+    }
+
+    /// The SVG document for the current frame.
+    pub fn to_svg(&self) -> String {
+        let (w, h) = (self.w, self.h);
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+             <g font-family=\"Open Sans, Arial, sans-serif\" font-size=\"20\">\n{}</g>\n</svg>\n",
+            self.elements
+        )
+    }
+}
+
+fn svgcol((r, g, b): Color) -> String {
+    format!("rgb({r},{g},{b})")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl Canvas for SvgCanvas {
+    fn fill_background(&mut self, color: Color) {
+        self.elements.clear();
+        self.fill_rect(0, 0, self.w, self.h, color);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        writeln!(
+            self.elements,
+            "<rect x=\"{x}\" y=\"{y}\" width=\"{w}\" height=\"{h}\" fill=\"{}\"/>",
+            svgcol(color)
+        )
+        .unwrap();
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        // Offset by half a pixel so the 1px stroke covers the same pixels as the SDL outline.
+        writeln!(
+            self.elements,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"{}\"/>",
+            x as f32 + 0.5,
+            y as f32 + 0.5,
+            w.saturating_sub(1),
+            h.saturating_sub(1),
+            svgcol(color)
+        )
+        .unwrap();
+    }
+
+    fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str) {
+        let anchor = match ha {
+            HAlign::Left => "start",
+            HAlign::Center => "middle",
+            HAlign::Right => "end",
+        };
+        let baseline = match va {
+            VAlign::Top => "hanging",
+            VAlign::Center => "central",
+            VAlign::Bottom => "text-after-edge",
+        };
+        writeln!(
+            self.elements,
+            "<text x=\"{x}\" y=\"{y}\" text-anchor=\"{anchor}\" dominant-baseline=\"{baseline}\" fill=\"{}\">{}</text>",
+            svgcol(BLACK),
+            escape(text)
+        )
+        .unwrap();
+    }
+
+    fn draw_line(&mut self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), color: Color) {
+        writeln!(
+            self.elements,
+            "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"{}\"/>",
+            svgcol(color)
+        )
+        .unwrap();
+    }
+
+    fn draw_curve(
+        &mut self,
+        (x1, y1): (i32, i32),
+        (cx, cy): (i32, i32),
+        (x2, y2): (i32, i32),
+        color: Color,
+    ) {
+        writeln!(
+            self.elements,
+            "<path d=\"M {x1} {y1} Q {cx} {cy} {x2} {y2}\" fill=\"none\" stroke=\"{}\"/>",
+            svgcol(color)
+        )
+        .unwrap();
+    }
+
+    fn save(&mut self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut path = self.dir.clone();
+        path.push(format!("{}", self.frame));
+        path.set_extension("svg");
+        std::fs::write(path, self.to_svg())?;
+        self.frame += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{self, Attribute},
+    terminal,
+};
+
+use super::CS;
+use crate::{
+    canvas::{Canvas, Color, HAlign, VAlign, BLACK},
+    interaction::KeyboardAction,
+};
+
+// Each grid cell is drawn as 3 terminal columns (padding, content, padding) on a single row.
+const CELL_COLS: i32 = 3;
+// Pixels per terminal column and row.
+const PX_COL: i32 = CS as i32 / CELL_COLS;
+const PX_ROW: i32 = CS as i32;
+// Rects at most this thin are lines rather than boxes.
+const LINE_WIDTH: u32 = 4;
+
+#[derive(Clone, Copy)]
+struct Cell {
+    c: char,
+    fg: Color,
+    bg: Color,
+    underline: Option<Color>,
+}
+
+/// Draws the cell grid in a terminal using ANSI colors.
+/// Backgrounds map to cell colors, highlight boxes to colored `┃` edges and underlines.
+pub struct TerminalCanvas {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+}
+
+impl TerminalCanvas {
+    /// Switches the terminal to raw mode on an alternate screen until `restore` is called,
+    /// the canvas is dropped or the program panics.
+    pub fn new(w: u32, h: u32) -> Self {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore();
+            hook(info);
+        }));
+        terminal::enable_raw_mode().unwrap();
+        execute!(stdout(), terminal::EnterAlternateScreen, cursor::Hide).unwrap();
+        let cols = (w as i32 / PX_COL) as usize;
+        let rows = (h as i32 / PX_ROW) as usize;
+        let empty = Cell {
+            c: ' ',
+            fg: BLACK,
+            bg: BLACK,
+            underline: None,
+        };
+        Self {
+            cols,
+            rows,
+            cells: vec![empty; cols * rows],
+        }
+    }
+
+    fn cell(&mut self, col: i32, row: i32) -> Option<&mut Cell> {
+        if 0 <= col && col < self.cols as i32 && 0 <= row && row < self.rows as i32 {
+            Some(&mut self.cells[row as usize * self.cols + col as usize])
+        } else {
+            None
+        }
+    }
+
+    fn vertical_line(&mut self, col: i32, rows: std::ops::Range<i32>, color: Color) {
+        for row in rows {
+            if let Some(cell) = self.cell(col, row) {
+                cell.c = '┃';
+                cell.fg = color;
+            }
+        }
+    }
+
+    /// Underline the given row, which draws a line below it.
+    fn horizontal_line(&mut self, cols: std::ops::Range<i32>, row: i32, color: Color) {
+        for col in cols {
+            if let Some(cell) = self.cell(col, row) {
+                cell.underline = Some(color);
+            }
+        }
+    }
+}
+
+impl Drop for TerminalCanvas {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leave raw mode and the alternate screen. Errors are ignored, since this also runs while
+/// panicking, and calling it again has no effect.
+pub fn restore() {
+    if terminal::is_raw_mode_enabled().unwrap_or(false) {
+        let _ = execute!(stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Write `text` below the grid of a canvas of height `h`, wrapped to the terminal width.
+pub fn write_below(h: u32, text: &str) {
+    let (tcols, trows) = terminal::size().unwrap();
+    let mut out = stdout();
+    let mut row = (h as i32 / PX_ROW) as u16 + 1;
+    let mut line = String::new();
+    let mut lines = vec![];
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > tcols as usize {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    for line in lines {
+        if row >= trows {
+            break;
+        }
+        queue!(out, cursor::MoveTo(0, row), style::Print(line)).unwrap();
+        row += 1;
+    }
+    out.flush().unwrap();
+}
+
+fn tcol((r, g, b): Color) -> style::Color {
+    style::Color::Rgb { r, g, b }
+}
+
+impl Canvas for TerminalCanvas {
+    fn fill_background(&mut self, color: Color) {
+        for cell in &mut self.cells {
+            *cell = Cell {
+                c: ' ',
+                fg: BLACK,
+                bg: color,
+                underline: None,
+            };
+        }
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        for row in y / PX_ROW..(y + h as i32) / PX_ROW {
+            for col in x / PX_COL..(x + w as i32) / PX_COL {
+                if let Some(cell) = self.cell(col, row) {
+                    cell.bg = color;
+                }
+            }
+        }
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        // The outline around a single character box; the background already separates cells.
+        if w == CS && h == CS && x % CS as i32 == 0 && y % CS as i32 == 0 {
+            return;
+        }
+        let (x2, y2) = (x + w as i32, y + h as i32);
+        if w <= LINE_WIDTH {
+            let col = (x + x2) / 2 / PX_COL;
+            self.vertical_line(col, y / PX_ROW..y2 / PX_ROW, color);
+        } else if h <= LINE_WIDTH {
+            let row = ((y + y2) / 2 + PX_ROW / 2) / PX_ROW - 1;
+            self.horizontal_line(x / PX_COL..x2 / PX_COL, row, color);
+        } else {
+            let rows = y / PX_ROW..(y2 - 1) / PX_ROW + 1;
+            let cols = x / PX_COL..(x2 - 1) / PX_COL + 1;
+            self.vertical_line(cols.start, rows.clone(), color);
+            self.vertical_line(cols.end - 1, rows.clone(), color);
+            self.horizontal_line(cols.clone(), rows.start - 1, color);
+            self.horizontal_line(cols, rows.end - 1, color);
+        }
+    }
+
+    /// Lines are dots in the empty cells they pass through.
+    fn draw_line(&mut self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), color: Color) {
+        let steps = ((x2 - x1).abs() / PX_COL)
+            .max((y2 - y1).abs() / PX_ROW)
+            .max(1);
+        for k in 0..=steps {
+            let x = x1 + (x2 - x1) * k / steps;
+            let y = y1 + (y2 - y1) * k / steps;
+            if let Some(cell) = self.cell(x / PX_COL, y / PX_ROW) {
+                if cell.c == ' ' {
+                    cell.c = '·';
+                    cell.fg = color;
+                }
+            }
+        }
+    }
+
+    fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str) {
+        let len = text.chars().count() as i32;
+        let col = x / PX_COL;
+        let col = match ha {
+            HAlign::Left => col,
+            HAlign::Center => col - len / 2,
+            HAlign::Right => col - len,
+        };
+        let row = match va {
+            VAlign::Top | VAlign::Center => y / PX_ROW,
+            VAlign::Bottom => (y - 1) / PX_ROW,
+        };
+        for (i, c) in text.chars().enumerate() {
+            if let Some(cell) = self.cell(col + i as i32, row) {
+                cell.c = c;
+                cell.fg = BLACK;
+            }
+        }
+    }
+
+    // no-op
+    fn save(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn present(&mut self) {
+        let (tcols, trows) = terminal::size().unwrap();
+        let cols = self.cols.min(tcols as usize);
+        let rows = self.rows.min(trows as usize);
+        let mut out = stdout();
+        queue!(out, terminal::Clear(terminal::ClearType::All)).unwrap();
+        for row in 0..rows {
+            queue!(out, cursor::MoveTo(0, row as u16)).unwrap();
+            for cell in &self.cells[row * self.cols..row * self.cols + cols] {
+                queue!(
+                    out,
+                    style::SetBackgroundColor(tcol(cell.bg)),
+                    style::SetForegroundColor(tcol(cell.fg)),
+                )
+                .unwrap();
+                if let Some(color) = cell.underline {
+                    queue!(
+                        out,
+                        style::SetUnderlineColor(tcol(color)),
+                        style::SetAttribute(Attribute::Underlined)
+                    )
+                    .unwrap();
+                }
+                queue!(out, style::Print(cell.c)).unwrap();
+                if cell.underline.is_some() {
+                    queue!(out, style::SetAttribute(Attribute::NoUnderline)).unwrap();
+                }
+            }
+            queue!(out, style::ResetColor).unwrap();
+        }
+        out.flush().unwrap();
+    }
+}
+
+/// Wait until a bound key is pressed or `timeout` has passed, which gives `None`.
+/// Other events are skipped, except that `redraw` is called when the terminal is resized.
+pub fn wait_for_key(timeout: Duration, mut redraw: impl FnMut()) -> KeyboardAction {
+    // `poll` can not handle `Duration::MAX`, so block on `read` instead when paused.
+    let deadline = (timeout != Duration::MAX)
+        .then(|| Instant::now().checked_add(timeout))
+        .flatten();
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !event::poll(remaining).unwrap() {
+                return KeyboardAction::None;
+            }
+        }
+        match event::read().unwrap() {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                let action = key_action(key);
+                if action != KeyboardAction::None {
+                    return action;
+                }
+            }
+            Event::Resize(..) => redraw(),
+            _ => {}
+        }
+    }
+}
+
+/// Same key bindings as the SDL window. `x` and Ctrl-C restore the terminal and exit.
+fn key_action(key: KeyEvent) -> KeyboardAction {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            restore();
+            KeyboardAction::Exit
+        }
+        KeyCode::Char('x') => {
+            restore();
+            KeyboardAction::Exit
+        }
+        KeyCode::Char(' ') | KeyCode::Right => KeyboardAction::Next,
+        KeyCode::Backspace | KeyCode::Left => KeyboardAction::Prev,
+        KeyCode::Char('p') | KeyCode::Enter => KeyboardAction::PausePlay,
+        KeyCode::Char('+') | KeyCode::Up | KeyCode::Char('f') => KeyboardAction::Faster,
+        KeyCode::Char('-') | KeyCode::Down | KeyCode::Char('s') => KeyboardAction::Slower,
+        KeyCode::Esc | KeyCode::Char('q') => KeyboardAction::ToEnd,
+        _ => KeyboardAction::None,
+    }
+}
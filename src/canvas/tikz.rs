@@ -0,0 +1,197 @@
+use std::{fmt::Write, path::PathBuf};
+
+use super::CS;
+use crate::canvas::{Canvas, Color, HAlign, VAlign, PALETTE};
+
+/// Renders each frame as a standalone TikZ picture and writes it to `dir/<frame>.tex` on `save()`.
+/// Coordinates are in grid cells, with y pointing down like `Pos`.
+pub struct TikzCanvas {
+    w: u32,
+    h: u32,
+    dir: PathBuf,
+    frame: usize,
+    /// Named colors; algorithm-specific names come first so they take precedence.
+    palette: Vec<(String, Color)>,
+    elements: String,
+}
+
+impl TikzCanvas {
+    pub fn new(w: u32, h: u32, dir: PathBuf, palette: Vec<(&'static str, Color)>) -> Self {
+        let palette = palette
+            .into_iter()
+            .chain(PALETTE)
+            .map(|(name, color)| (name.to_string(), color))
+            .collect();
+        Self {
+            w,
+            h,
+            dir,
+            frame: 0,
+            palette,
+            elements: String::new(),
+        }
+    }
+
+    /// The name of a color, defining a new one for colors not in the palette.
+    fn color(&mut self, color: Color) -> String {
+        if let Some((name, _)) = self.palette.iter().find(|(_, c)| *c == color) {
+            return name.clone();
+        }
+        let (r, g, b) = color;
+        let name = format!("c{r:02x}{g:02x}{b:02x}");
+        self.palette.push((name.clone(), color));
+        name
+    }
+
+    /// The standalone LaTeX document for the current frame.
+    pub fn to_tex(&self) -> String {
+        let mut tex = String::from("\\documentclass[tikz]{standalone}\n");
+        for (name, (r, g, b)) in &self.palette {
+            writeln!(tex, "\\definecolor{{{name}}}{{RGB}}{{{r},{g},{b}}}").unwrap();
+        }
+        tex += "\\begin{document}\n";
+        tex += "\\begin{tikzpicture}[x=0.6cm, y=-0.6cm, every node/.style={font=\\sffamily\\small, inner sep=1pt}]\n";
+        tex += &self.elements;
+        tex += "\\end{tikzpicture}\n\\end{document}\n";
+        tex
+    }
+}
+
+/// Convert pixels to cells.
+fn c(px: i32) -> String {
+    let s = format!("{:.3}", px as f32 / CS as f32);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Escape text for pdflatex, which only supports ASCII without extra packages.
+/// Other characters than the symbols used in the visualizations become `?`.
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|ch| match ch {
+            '$' | '#' | '%' | '&' | '_' | '{' | '}' => format!("\\{ch}"),
+            '<' => "\\textless{}".to_string(),
+            '>' => "\\textgreater{}".to_string(),
+            '~' => "\\textasciitilde{}".to_string(),
+            '^' => "\\textasciicircum{}".to_string(),
+            '\\' => "\\textbackslash{}".to_string(),
+            'σ' => "$\\sigma$".to_string(),
+            'δ' => "$\\delta$".to_string(),
+            '≤' => "$\\leq$".to_string(),
+            '≠' => "$\\neq$".to_string(),
+            '→' => "$\\to$".to_string(),
+            '←' => "$\\leftarrow$".to_string(),
+            ch if !ch.is_ascii() => "?".to_string(),
+            ch => ch.to_string(),
+        })
+        .collect()
+}
+
+impl Canvas for TikzCanvas {
+    fn fill_background(&mut self, color: Color) {
+        self.elements.clear();
+        self.fill_rect(0, 0, self.w, self.h, color);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        let color = self.color(color);
+        let (x2, y2) = (x + w as i32, y + h as i32);
+        writeln!(
+            self.elements,
+            "\\fill[{color}] ({}, {}) rectangle ({}, {});",
+            c(x),
+            c(y),
+            c(x2),
+            c(y2)
+        )
+        .unwrap();
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: Color) {
+        let color = self.color(color);
+        let (x2, y2) = (x + w as i32, y + h as i32);
+        writeln!(
+            self.elements,
+            "\\draw[{color}] ({}, {}) rectangle ({}, {});",
+            c(x),
+            c(y),
+            c(x2),
+            c(y2)
+        )
+        .unwrap();
+    }
+
+    fn write_text(&mut self, x: i32, y: i32, ha: HAlign, va: VAlign, text: &str) {
+        let v = match va {
+            VAlign::Top => "north",
+            VAlign::Center => "",
+            VAlign::Bottom => "south",
+        };
+        let h = match ha {
+            HAlign::Left => "west",
+            HAlign::Center => "",
+            HAlign::Right => "east",
+        };
+        let anchor = match (v, h) {
+            ("", "") => "center".to_string(),
+            (v, "") => v.to_string(),
+            ("", h) => h.to_string(),
+            (v, h) => format!("{v} {h}"),
+        };
+        writeln!(
+            self.elements,
+            "\\node[anchor={anchor}] at ({}, {}) {{{}}};",
+            c(x),
+            c(y),
+            escape(text)
+        )
+        .unwrap();
+    }
+
+    fn draw_line(&mut self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), color: Color) {
+        let color = self.color(color);
+        writeln!(
+            self.elements,
+            "\\draw[{color}] ({}, {}) -- ({}, {});",
+            c(x1),
+            c(y1),
+            c(x2),
+            c(y2)
+        )
+        .unwrap();
+    }
+
+    fn draw_curve(
+        &mut self,
+        (x1, y1): (i32, i32),
+        (cx, cy): (i32, i32),
+        (x2, y2): (i32, i32),
+        color: Color,
+    ) {
+        let color = self.color(color);
+        // TikZ curves are cubic; these control points give the same quadratic curve.
+        let third = |a: i32, b: i32| a + 2 * (b - a) / 3;
+        writeln!(
+            self.elements,
+            "\\draw[{color}] ({}, {}) .. controls ({}, {}) and ({}, {}) .. ({}, {});",
+            c(x1),
+            c(y1),
+            c(third(x1, cx)),
+            c(third(y1, cy)),
+            c(third(x2, cx)),
+            c(third(y2, cy)),
+            c(x2),
+            c(y2)
+        )
+        .unwrap();
+    }
+
+    fn save(&mut self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut path = self.dir.clone();
+        path.push(format!("{}", self.frame));
+        path.set_extension("tex");
+        std::fs::write(path, self.to_tex())?;
+        self.frame += 1;
+        Ok(())
+    }
+}
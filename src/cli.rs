@@ -1,4 +1,4 @@
-use clap::{Parser, ValueEnum};
+use clap::{ArgGroup, Parser, ValueEnum};
 use std::path::PathBuf;
 
 lazy_static! {
@@ -8,12 +8,27 @@ lazy_static! {
 #[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
 pub enum Algorithm {
     SuffixArray,
+    Sais,
+    PrefixDoubling,
+    DC3,
+    LCP,
+    Ukkonen,
+    LcpTree,
+    SuffixAutomaton,
     BWT,
+    ApproxSearch,
     BiBWT,
+    WaveletTree,
 }
 
 #[derive(Parser)]
 #[clap(author, about)]
+// Only one output can be written or shown at a time.
+#[clap(group(
+    ArgGroup::new("output")
+        .multiple(false)
+        .args(&["save", "svg", "tikz", "gif", "apng", "trace", "tui"])
+))]
 pub struct Cli {
     /// Algorithm to run
     #[clap(value_enum)]
@@ -23,11 +38,143 @@ pub struct Cli {
     #[clap()]
     pub input: Option<String>,
 
+    /// Read the string from a plain text, FASTA or FASTQ file.
+    #[clap(long, parse(from_os_str), conflicts_with = "input")]
+    pub input_file: Option<PathBuf>,
+
+    /// Id or 1-based position of the FASTA/FASTQ record to read from --input-file. Defaults to the
+    /// first record.
+    #[clap(long, requires = "input-file")]
+    pub input_record: Option<String>,
+
+    /// For suffix-array, first show how Ko-Aluru sorts the small suffixes recursively.
+    #[clap(long)]
+    pub full: bool,
+
     /// Query string for BWT.
     #[clap(short, long)]
     pub query: Option<String>,
 
-    /// Where to optionally save image files.
+    /// Read the query from a plain text, FASTA or FASTQ file.
+    #[clap(long, parse(from_os_str), conflicts_with = "query")]
+    pub query_file: Option<PathBuf>,
+
+    /// Id or 1-based position of the FASTA/FASTQ record to read from --query-file. Defaults to the
+    /// first record.
+    #[clap(long, requires = "query-file")]
+    pub query_record: Option<String>,
+
+    /// For bwt, keep the suffix array entries that are a multiple of this to locate occurrences.
+    #[clap(long, default_value_t = 4)]
+    pub sample_rate: usize,
+
+    /// For bwt, only store every this many rows of Occ and scan L for the rest.
+    #[clap(long, default_value_t = 1)]
+    pub checkpoint: usize,
+
+    /// For bi-bwt, search with mismatches using this search scheme, given as order/lower/upper
+    /// per search with parts numbered from 1, e.g. 12/00/01,21/01/01.
+    #[clap(long)]
+    pub scheme: Option<String>,
+
+    /// For bi-bwt, the position of the query where exact matching starts. Defaults to the middle.
+    #[clap(long, conflicts_with = "scheme")]
+    pub start: Option<usize>,
+
+    /// For bi-bwt, the sides of the extensions after the first character, e.g. LRRLR.
+    /// Defaults to all extensions to the left, followed by those to the right.
+    #[clap(long, conflicts_with = "scheme")]
+    pub order: Option<String>,
+
+    /// For approx-search, the maximal number of mismatches.
+    #[clap(short = 'k', long, default_value_t = 1)]
+    pub mismatches: usize,
+
+    /// Write each frame as a .png file to this directory, without opening a window.
     #[clap(short, long, parse(from_os_str))]
     pub save: Option<PathBuf>,
+
+    /// Write each frame as an .svg file to this directory, without opening a window.
+    #[clap(long, parse(from_os_str))]
+    pub svg: Option<PathBuf>,
+
+    /// Write each frame as a standalone TikZ .tex file to this directory, without opening a window.
+    #[clap(long, parse(from_os_str))]
+    pub tikz: Option<PathBuf>,
+
+    /// Write all frames as an animated .gif to this file, without opening a window.
+    #[clap(long, parse(from_os_str))]
+    pub gif: Option<PathBuf>,
+
+    /// Write all frames as an animated .png to this file, without opening a window.
+    #[clap(long, parse(from_os_str))]
+    pub apng: Option<PathBuf>,
+
+    /// Write the data of every state as JSON lines to this file, without opening a window.
+    #[clap(long, parse(from_os_str))]
+    pub trace: Option<PathBuf>,
+
+    /// Print the explanation of each shown state to stdout, or below the visualization with --tui.
+    #[clap(long)]
+    pub explain: bool,
+
+    /// Show the visualization in the terminal instead of an SDL window.
+    #[clap(long)]
+    pub tui: bool,
+
+    /// Seconds each frame is shown, both when playing and in animations.
+    #[clap(short, long, default_value_t = 1.0, value_parser = parse_delay)]
+    pub delay: f32,
+}
+
+/// Parse a delay, which must be a positive number of seconds.
+fn parse_delay(s: &str) -> Result<f32, String> {
+    let delay: f32 = s.parse().map_err(|err| format!("{err}"))?;
+    if delay.is_finite() && delay > 0.0 {
+        Ok(delay)
+    } else {
+        Err(format!(
+            "The delay must be a positive number of seconds, not {s}."
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{ErrorKind, Parser};
+
+    use super::{Algorithm, Cli};
+
+    #[test]
+    fn unknown_algorithm() {
+        let err = Cli::try_parse_from(["alg-viz", "foo"]).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidValue);
+        let args = Cli::try_parse_from(["alg-viz", "dc3"]).unwrap();
+        assert!(matches!(args.algorithm, Algorithm::DC3));
+    }
+
+    #[test]
+    fn conflicting_outputs() {
+        let err = Cli::try_parse_from(["alg-viz", "bwt", "--save", "x", "--svg", "y"])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        let err = Cli::try_parse_from(["alg-viz", "bwt", "--gif", "x.gif", "--tui"])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        assert!(Cli::try_parse_from(["alg-viz", "bwt", "--save", "x", "--explain"]).is_ok());
+    }
+
+    #[test]
+    fn invalid_delay() {
+        for delay in ["-1", "0", "NaN", "inf", "abc"] {
+            let err = Cli::try_parse_from(["alg-viz", "bwt", &format!("--delay={delay}")])
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), ErrorKind::ValueValidation);
+        }
+        let args = Cli::try_parse_from(["alg-viz", "bwt", "--delay", "0.5"]).unwrap();
+        assert_eq!(args.delay, 0.5);
+    }
 }
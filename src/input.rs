@@ -0,0 +1,133 @@
+use std::path::Path;
+
+/// Read a sequence from a plain text, FASTA or FASTQ file.
+///
+/// Plain text files are read as a single sequence.
+/// For FASTA and FASTQ, the record with the given id is used, or the first record by default.
+/// A number that is not the id of a record selects the record at that position, counting from 1.
+/// Line breaks and other whitespace are removed and the sequence is converted to uppercase.
+pub fn read_sequence(path: &Path, record: Option<&str>) -> Result<String, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {err}", path.display()))?;
+    let records = match text.trim_start().chars().next() {
+        Some('>') => fasta_records(&text),
+        Some('@') => fastq_records(&text),
+        _ => {
+            if record.is_some() {
+                return Err(format!(
+                    "{} is not a FASTA or FASTQ file, so it has no named records.",
+                    path.display()
+                ));
+            }
+            vec![("", text.as_str())]
+        }
+    };
+    let (_, seq) = match record {
+        None => records.first(),
+        Some(name) => records.iter().find(|(id, _)| *id == name).or_else(|| {
+            let index = name.parse::<usize>().ok()?;
+            records.get(index.checked_sub(1)?)
+        }),
+    }
+    .ok_or_else(|| match record {
+        None => format!("{} contains no records.", path.display()),
+        Some(name) => format!("{} has no record named {name}.", path.display()),
+    })?;
+    Ok(normalize(seq))
+}
+
+/// Remove all whitespace and convert to uppercase.
+fn normalize(seq: &str) -> String {
+    seq.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// The id of a record is its header up to the first whitespace.
+fn record_id(header: &str) -> &str {
+    header.split_whitespace().next().unwrap_or("")
+}
+
+/// (id, sequence) pairs, where the sequence may span multiple lines.
+fn fasta_records(text: &str) -> Vec<(&str, &str)> {
+    text.split('>')
+        .skip(1)
+        .map(|record| {
+            let (header, seq) = record.split_once('\n').unwrap_or((record, ""));
+            (record_id(header), seq)
+        })
+        .collect()
+}
+
+/// (id, sequence) pairs of records of four lines: header, sequence, `+` and qualities.
+fn fastq_records(text: &str) -> Vec<(&str, &str)> {
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+    lines
+        .chunks(4)
+        .filter_map(|record| {
+            let header = record[0].strip_prefix('@')?;
+            Some((record_id(header), *record.get(1)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::read_sequence;
+
+    /// Write `text` to a temporary file named after the test.
+    fn file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("alg-viz-{}-{name}", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn fasta() {
+        let path = file(
+            "fasta",
+            ">chr1 first record\nacgt\nTTGA\n\n>chr2\nGGCC\nAA\n>3\nCAT\n",
+        );
+        assert_eq!(read_sequence(&path, None).unwrap(), "ACGTTTGA");
+        assert_eq!(read_sequence(&path, Some("chr2")).unwrap(), "GGCCAA");
+        assert_eq!(read_sequence(&path, Some("2")).unwrap(), "GGCCAA");
+        // Ids take precedence over positions.
+        assert_eq!(read_sequence(&path, Some("3")).unwrap(), "CAT");
+        assert!(read_sequence(&path, Some("chr3")).is_err());
+        assert!(read_sequence(&path, Some("0")).is_err());
+        assert!(read_sequence(&path, Some("4")).is_err());
+    }
+
+    #[test]
+    fn fastq() {
+        let path = file(
+            "fastq",
+            "@read1 lane 1\nACGT\n+\n@@@@\n@read2\nggaa\n+read2\nIIII\n",
+        );
+        assert_eq!(read_sequence(&path, None).unwrap(), "ACGT");
+        assert_eq!(read_sequence(&path, Some("read2")).unwrap(), "GGAA");
+        assert_eq!(read_sequence(&path, Some("2")).unwrap(), "GGAA");
+        assert!(read_sequence(&path, Some("read3")).is_err());
+    }
+
+    #[test]
+    fn plain_text() {
+        let path = file("plain", "  banana\nbread \n");
+        assert_eq!(read_sequence(&path, None).unwrap(), "BANANABREAD");
+        assert!(read_sequence(&path, Some("1")).is_err());
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = std::env::temp_dir().join("alg-viz-does-not-exist");
+        assert!(read_sequence(&path, None)
+            .unwrap_err()
+            .starts_with("Could not read"));
+    }
+}
@@ -72,6 +72,13 @@ impl Interaction {
     pub fn get(&self) -> usize {
         self.idx
     }
+    /// Time each frame is shown while playing.
+    pub fn spf(&self) -> Duration {
+        self.spf
+    }
+    pub fn set_spf(&mut self, spf: Duration) {
+        self.spf = spf;
+    }
     pub fn faster(&mut self) {
         self.spf = self.spf.div_f32(1.5);
     }
@@ -82,15 +89,17 @@ impl Interaction {
         self.playing = !self.playing;
     }
 
-    #[cfg(feature = "bin")]
-    pub fn wait(&mut self) {
-        use crate::canvas::sdl::wait_for_key;
-
-        match wait_for_key(if self.playing {
+    /// How long to wait for a key before moving on to the next frame.
+    pub fn timeout(&self) -> Duration {
+        if self.playing {
             self.spf
         } else {
             Duration::MAX
-        }) {
+        }
+    }
+
+    pub fn handle(&mut self, action: KeyboardAction) {
+        match action {
             KeyboardAction::Next => {
                 self.next();
             }
@@ -112,6 +121,13 @@ impl Interaction {
             }
         }
     }
+
+    #[cfg(feature = "sdl")]
+    pub fn wait(&mut self) {
+        use crate::canvas::sdl::wait_for_key;
+
+        self.handle(wait_for_key(self.timeout()));
+    }
     #[cfg(feature = "wasm")]
     pub fn wait(&mut self) {}
 }
@@ -8,6 +8,8 @@ pub mod interaction;
 
 #[cfg(feature = "bin")]
 pub mod cli;
+#[cfg(feature = "bin")]
+pub mod input;
 
 #[cfg(feature = "bin")]
 #[macro_use]
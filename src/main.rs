@@ -1,39 +1,92 @@
 #![cfg(feature = "bin")]
 #![feature(duration_constants)]
 
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use alg_viz::{
     alg::Viz,
-    alg::{bibwt, bwt, suffix_array as sa},
-    canvas::sdl::new_canvas,
+    alg::{
+        approx_search, bibwt, bwt, dc3, lcp, lcp_tree, prefix_doubling, sais, suffix_array as sa,
+        suffix_automaton, ukkonen, wavelet_tree,
+    },
+    canvas::animation::{AnimationCanvas, AnimationFormat},
+    canvas::raster::RasterCanvas,
+    canvas::svg::SvgCanvas,
+    canvas::tikz::TikzCanvas,
     canvas::CanvasBox,
     cli::{Algorithm, ARGS},
+    input::read_sequence,
     interaction::Interaction,
 };
 
-fn main() -> ! {
-    let mut s = ARGS
-        .input
-        .clone()
-        .unwrap_or("GTCCCGATGTCATGTCAGGA".to_owned());
-    s.push('$');
-    let s = s.into_bytes();
-    let q = ARGS
-        .query
-        .clone()
-        .unwrap_or("GTCC".to_string())
-        .into_bytes();
+fn print_explanation(alg: &dyn Viz, state: usize) {
+    if ARGS.explain {
+        let explanation = alg.explanation(state);
+        println!("{}\n{}\n", explanation.title, explanation.description);
+    }
+}
 
-    let alg = match ARGS.algorithm {
-        Algorithm::SuffixArray => Box::new(sa::SA::new(s)) as Box<dyn Viz>,
-        Algorithm::BWT => Box::new(bwt::BWT::new(s, q)) as Box<dyn Viz>,
-        Algorithm::BiBWT => Box::new(bibwt::BiBWT::new(s, q)) as Box<dyn Viz>,
-    };
+/// Draw every state that is shown and save it as a separate frame.
+fn save_frames(alg: &dyn Viz, mut canvas: CanvasBox) -> std::io::Result<()> {
+    for state in 0..alg.num_states() {
+        if alg.draw(state, &mut canvas) {
+            print_explanation(alg, state);
+            canvas.save()?;
+        }
+    }
+    canvas.finish()
+}
+
+/// Write the trace of every state as a JSON object per line.
+fn write_trace(alg: &dyn Viz, path: &Path) -> std::io::Result<()> {
+    let mut f = File::create(path)?;
+    for state in 0..alg.num_states() {
+        writeln!(f, "{}", alg.trace(state))?;
+    }
+    Ok(())
+}
+
+fn run_terminal(alg: &dyn Viz, mut interaction: Interaction) -> ! {
+    use alg_viz::canvas::terminal::{wait_for_key, write_below, TerminalCanvas};
+
+    let (w, h) = alg.canvas_size();
+    let mut canvas = Box::new(TerminalCanvas::new(w as u32, h as u32)) as CanvasBox;
+    loop {
+        if alg.draw(interaction.get(), &mut canvas) {
+            let state = interaction.get();
+            let mut present = || {
+                canvas.present();
+                if ARGS.explain {
+                    // The title is already drawn on the canvas.
+                    write_below(h as u32, &alg.explanation(state).description);
+                }
+            };
+            present();
+            interaction.handle(wait_for_key(interaction.timeout(), present));
+        } else {
+            interaction.step();
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn run_interactive(alg: &dyn Viz, mut interaction: Interaction) -> ! {
+    use alg_viz::canvas::sdl::new_canvas;
 
     let (w, h) = alg.canvas_size();
     let ref mut canvas = Box::new(new_canvas(w as u32, h as u32)) as CanvasBox;
-    let mut interaction = Interaction::new(alg.num_states());
+    let mut last_shown = None;
     loop {
         if alg.draw(interaction.get(), canvas) {
+            if last_shown != Some(interaction.get()) {
+                print_explanation(alg, interaction.get());
+                last_shown = Some(interaction.get());
+            }
             canvas.present();
             interaction.wait();
         } else {
@@ -41,3 +94,118 @@ fn main() -> ! {
         }
     }
 }
+
+#[cfg(not(feature = "sdl"))]
+fn run_interactive(_alg: &dyn Viz, _interaction: Interaction) -> ! {
+    eprintln!("Built without the `sdl` feature; use --tui, or --save, --svg, --tikz, --gif or --apng to write frames instead.");
+    std::process::exit(1);
+}
+
+fn main() {
+    let read = |path: &Option<PathBuf>, record: &Option<String>| {
+        path.as_ref().map(|path| {
+            read_sequence(path, record.as_deref()).unwrap_or_else(|err| {
+                eprintln!("{err}");
+                std::process::exit(1);
+            })
+        })
+    };
+    let mut s = read(&ARGS.input_file, &ARGS.input_record)
+        .or(ARGS.input.clone())
+        .unwrap_or("GTCCCGATGTCATGTCAGGA".to_owned());
+    s.push('$');
+    let s = s.into_bytes();
+    let q = read(&ARGS.query_file, &ARGS.query_record)
+        .or(ARGS.query.clone())
+        .unwrap_or("GTCC".to_string())
+        .into_bytes();
+
+    let alg = match ARGS.algorithm {
+        Algorithm::SuffixArray => {
+            sa::SA::new(s, ARGS.full).map(|alg| Box::new(alg) as Box<dyn Viz>)
+        }
+        Algorithm::Sais => sais::SAIS::new(s).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::PrefixDoubling => {
+            prefix_doubling::PrefixDoubling::new(s).map(|alg| Box::new(alg) as Box<dyn Viz>)
+        }
+        Algorithm::DC3 => dc3::DC3::new(s).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::LCP => lcp::LCP::new(s).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::Ukkonen => ukkonen::Ukkonen::new(s).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::LcpTree => lcp_tree::LcpTree::new(s).map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::SuffixAutomaton => {
+            suffix_automaton::SuffixAutomaton::new(s, q).map(|alg| Box::new(alg) as Box<dyn Viz>)
+        }
+        Algorithm::BWT => bwt::BWT::new(s, q, ARGS.sample_rate, ARGS.checkpoint)
+            .map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::ApproxSearch => approx_search::ApproxSearch::new(s, q, ARGS.mismatches)
+            .map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::BiBWT => ARGS
+            .order
+            .as_deref()
+            .map_or(Ok(vec![]), bibwt::parse_order)
+            .and_then(|order| {
+                let scheme = ARGS
+                    .scheme
+                    .as_deref()
+                    .map_or(Ok(vec![]), bibwt::parse_scheme)?;
+                bibwt::BiBWT::new(s, q, ARGS.start, order, scheme)
+            })
+            .map(|alg| Box::new(alg) as Box<dyn Viz>),
+        Algorithm::WaveletTree => {
+            wavelet_tree::WaveletTree::new(s, q).map(|alg| Box::new(alg) as Box<dyn Viz>)
+        }
+    };
+    let alg = alg.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    let mut interaction = Interaction::new(alg.num_states());
+    interaction.set_spf(Duration::from_secs_f32(ARGS.delay));
+
+    let (w, h) = (alg.canvas_size().0 as u32, alg.canvas_size().1 as u32);
+    let spf = interaction.spf();
+    if let Some(path) = &ARGS.trace {
+        if let Err(err) = write_trace(&*alg, path) {
+            eprintln!("Could not write {}: {err}", path.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let animation = |path: &PathBuf, format| {
+        let canvas = AnimationCanvas::new(w, h, path.clone(), format, spf).unwrap_or_else(|err| {
+            eprintln!("Could not write {}: {err}", path.display());
+            std::process::exit(1);
+        });
+        Box::new(canvas) as CanvasBox
+    };
+    let (canvas, path) = if let Some(dir) = &ARGS.save {
+        (
+            Box::new(RasterCanvas::new(w, h, Some(dir.clone()))) as CanvasBox,
+            dir,
+        )
+    } else if let Some(dir) = &ARGS.svg {
+        (
+            Box::new(SvgCanvas::new(w, h, dir.clone())) as CanvasBox,
+            dir,
+        )
+    } else if let Some(dir) = &ARGS.tikz {
+        (
+            Box::new(TikzCanvas::new(w, h, dir.clone(), alg.palette())) as CanvasBox,
+            dir,
+        )
+    } else if let Some(path) = &ARGS.gif {
+        (animation(path, AnimationFormat::Gif), path)
+    } else if let Some(path) = &ARGS.apng {
+        (animation(path, AnimationFormat::Apng), path)
+    } else if ARGS.tui {
+        run_terminal(&*alg, interaction);
+    } else {
+        run_interactive(&*alg, interaction);
+    };
+    if let Err(err) = save_frames(&*alg, canvas) {
+        eprintln!("Could not write {}: {err}", path.display());
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,280 @@
+//! Golden snapshots of the draw calls and the trace of every state.
+//! Run with `UPDATE_SNAPSHOTS=1` to rewrite the stored snapshots after an intended change.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use alg_viz::{
+    alg::{
+        approx_search::ApproxSearch,
+        bibwt::{parse_order, parse_scheme, BiBWT},
+        bwt::BWT,
+        dc3::DC3,
+        lcp::LCP,
+        lcp_tree::LcpTree,
+        prefix_doubling::PrefixDoubling,
+        sais::SAIS,
+        suffix_array::SA,
+        suffix_automaton::SuffixAutomaton,
+        ukkonen::Ukkonen,
+        wavelet_tree::WaveletTree,
+        Viz,
+    },
+    canvas::{
+        recording::{record, Op},
+        tikz::TikzCanvas,
+        CanvasBox,
+    },
+};
+
+/// Read the stored snapshot at `path`, or write `items` to it when updating snapshots.
+fn stored<T: serde::Serialize + serde::de::DeserializeOwned>(
+    path: &Path,
+    items: &[T],
+) -> Option<Vec<T>> {
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        let lines: String = items
+            .iter()
+            .map(|item| serde_json::to_string(item).unwrap() + "\n")
+            .collect();
+        std::fs::write(path, lines).unwrap();
+        return None;
+    }
+
+    let stored = std::fs::read_to_string(path)
+        .unwrap_or_else(|_| panic!("No snapshot at {path:?}; run with UPDATE_SNAPSHOTS=1."));
+    Some(
+        stored
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect(),
+    )
+}
+
+fn check(name: &str, alg: &dyn Viz) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    check_trace(name, alg, &dir.join(name).with_extension("trace.jsonl"));
+
+    let frames = record(alg);
+    let Some(stored) = stored(&dir.join(name).with_extension("jsonl"), &frames) else {
+        return;
+    };
+    for (frame, stored) in frames.iter().zip(&stored) {
+        assert_eq!(
+            frame.state, stored.state,
+            "{name}: different states are shown"
+        );
+        for (i, (op, stored_op)) in frame.ops.iter().zip(&stored.ops).enumerate() {
+            assert_eq!(
+                op, stored_op,
+                "{name}: state {} differs at op {i}",
+                frame.state
+            );
+        }
+        assert_eq!(
+            frame.ops.len(),
+            stored.ops.len(),
+            "{name}: state {} has a different number of ops",
+            frame.state
+        );
+    }
+    assert_eq!(
+        frames.len(),
+        stored.len(),
+        "{name}: different number of frames"
+    );
+}
+
+/// Compare the trace of every state, including those that are not drawn.
+fn check_trace(name: &str, alg: &dyn Viz, path: &Path) {
+    let traces: Vec<Value> = (0..alg.num_states())
+        .map(|state| {
+            let trace = alg.trace(state);
+            assert!(
+                trace.is_object(),
+                "{name}: trace of state {state} is not an object"
+            );
+            trace
+        })
+        .collect();
+    let Some(stored) = stored(path, &traces) else {
+        return;
+    };
+    for (state, (trace, stored)) in traces.iter().zip(&stored).enumerate() {
+        assert_eq!(trace, stored, "{name}: trace of state {state} differs");
+    }
+    assert_eq!(
+        traces.len(),
+        stored.len(),
+        "{name}: different number of traced states"
+    );
+}
+
+/// Compare the TikZ picture of the first frame that writes `glyph`, which must be escaped to
+/// plain ASCII for pdflatex.
+fn check_tikz(name: &str, alg: &dyn Viz, glyph: char) {
+    let frame = record(alg)
+        .into_iter()
+        .find(|frame| {
+            frame
+                .ops
+                .iter()
+                .any(|op| matches!(op, Op::WriteText(.., text) if text.contains(glyph)))
+        })
+        .unwrap_or_else(|| panic!("{name}: no frame writes {glyph}"));
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let (w, h) = alg.canvas_size();
+    let mut canvas = Box::new(TikzCanvas::new(
+        w as u32,
+        h as u32,
+        dir.clone(),
+        alg.palette(),
+    )) as CanvasBox;
+    alg.draw(frame.state, &mut canvas);
+    canvas.save().unwrap();
+    let tex = std::fs::read_to_string(dir.join("0.tex")).unwrap();
+    assert!(
+        tex.is_ascii(),
+        "{name}: the TikZ picture is not plain ASCII"
+    );
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(name)
+        .with_extension("tex");
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, tex).unwrap();
+        return;
+    }
+    let stored = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("No snapshot at {path:?}; run with UPDATE_SNAPSHOTS=1."));
+    assert_eq!(tex, stored, "{name}: the TikZ picture differs");
+}
+
+fn s(text: &str) -> Vec<u8> {
+    (text.to_string() + "$").into_bytes()
+}
+
+#[test]
+fn suffix_array() {
+    check("sa-acgtac", &SA::new(s("ACGTAC"), false).unwrap());
+    check("sa-banana", &SA::new(s("BANANA"), false).unwrap());
+    check(
+        "sa-full-mississippi",
+        &SA::new(s("MISSISSIPPI"), true).unwrap(),
+    );
+}
+
+#[test]
+fn sais() {
+    check("sais-abababab", &SAIS::new(s("ABABABAB")).unwrap());
+}
+
+#[test]
+fn prefix_doubling() {
+    check("pd-banana", &PrefixDoubling::new(s("BANANA")).unwrap());
+}
+
+#[test]
+fn dc3() {
+    check("dc3-banana", &DC3::new(s("BANANA")).unwrap());
+    check("dc3-abcabcabca", &DC3::new(s("ABCABCABCA")).unwrap());
+}
+
+#[test]
+fn lcp() {
+    check("lcp-banana", &LCP::new(s("BANANA")).unwrap());
+}
+
+#[test]
+fn ukkonen() {
+    check("ukkonen-abcabx", &Ukkonen::new(s("ABCABX")).unwrap());
+}
+
+#[test]
+fn lcp_tree() {
+    check("lcp-tree-banana", &LcpTree::new(s("BANANA")).unwrap());
+}
+
+#[test]
+fn suffix_automaton() {
+    check(
+        "sam-abcbcb-bcb",
+        &SuffixAutomaton::new(s("ABCBCB"), b"BCB".to_vec()).unwrap(),
+    );
+}
+
+#[test]
+fn bwt() {
+    check(
+        "bwt-acgtac-gta",
+        &BWT::new(s("ACGTAC"), b"GTA".to_vec(), 4, 1).unwrap(),
+    );
+    check(
+        "bwt-acgtac-tt",
+        &BWT::new(s("ACGTAC"), b"TT".to_vec(), 4, 1).unwrap(),
+    );
+    check(
+        "bwt-banana-ana",
+        &BWT::new(s("BANANA"), b"ANA".to_vec(), 4, 1).unwrap(),
+    );
+    check(
+        "bwt-banana-ana-checkpoint",
+        &BWT::new(s("BANANA"), b"ANA".to_vec(), 4, 3).unwrap(),
+    );
+    check_tikz(
+        "bwt-banana-ana-locate",
+        &BWT::new(s("BANANA"), b"ANA".to_vec(), 4, 1).unwrap(),
+        '→',
+    );
+}
+
+#[test]
+fn approx_search() {
+    check(
+        "approx-banana-ana",
+        &ApproxSearch::new(s("BANANA"), b"ANA".to_vec(), 1).unwrap(),
+    );
+}
+
+#[test]
+fn bibwt() {
+    check(
+        "bibwt-acgtac-gta",
+        &BiBWT::new(s("ACGTAC"), b"GTA".to_vec(), None, vec![], vec![]).unwrap(),
+    );
+    check(
+        "bibwt-banana-ana",
+        &BiBWT::new(s("BANANA"), b"ANA".to_vec(), None, vec![], vec![]).unwrap(),
+    );
+    check(
+        "bibwt-banana-ana-order",
+        &BiBWT::new(
+            s("BANANA"),
+            b"ANA".to_vec(),
+            None,
+            parse_order("RL").unwrap(),
+            vec![],
+        )
+        .unwrap(),
+    );
+    check(
+        "bibwt-banana-ana-scheme",
+        &BiBWT::new(
+            s("BANANA"),
+            b"ANA".to_vec(),
+            None,
+            vec![],
+            parse_scheme("12/00/01,21/01/01").unwrap(),
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn wavelet_tree() {
+    check(
+        "wt-banana-ana",
+        &WaveletTree::new(s("BANANA"), b"ANA".to_vec()).unwrap(),
+    );
+}